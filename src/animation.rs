@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
+use cgmath::{Matrix4, Vector3, Quaternion, InnerSpace, VectorSpace};
+
+use crate::scene::{Scene, NodeId};
+use crate::behaviour::{Behaviour, Frame};
+use crate::eye_rotation::interpolate;
+
+/// Node transform split into translation, rotation and non-uniform scale, like glTF nodes.
+#[derive(Debug, Copy, Clone)]
+pub struct Trs {
+	pub translation: Vector3<f32>,
+	pub rotation: Quaternion<f32>,
+	pub scale: Vector3<f32>,
+}
+
+impl From<Trs> for Matrix4<f32> {
+	fn from(trs: Trs) -> Matrix4<f32> {
+		Matrix4::from_translation(trs.translation)
+		* Matrix4::from(trs.rotation)
+		* Matrix4::from_nonuniform_scale(trs.scale.x, trs.scale.y, trs.scale.z)
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+	Step,
+	Linear,
+	/// Keyframes hold an in-tangent, value and out-tangent of each value.
+	CubicSpline,
+}
+
+#[derive(Debug, Clone)]
+pub enum Values {
+	Translation(Vec<Vector3<f32>>),
+	Rotation(Vec<Quaternion<f32>>),
+	Scale(Vec<Vector3<f32>>),
+	/// Weights of all morph targets for each keyframe.
+	MorphWeights(Vec<f32>),
+}
+
+/// Keyframes of a single property of a node.
+#[derive(Debug, Clone)]
+pub struct Channel {
+	pub node: NodeId,
+	/// Keyframe times in seconds, ascending.
+	pub times: Vec<f32>,
+	pub interpolation: Interpolation,
+	pub values: Values,
+}
+
+impl Channel {
+	/// Number of values in each keyframe, `None` if the keyframes don't match the values.
+	pub fn stride(&self) -> Option<usize> {
+		let values = match &self.values {
+			Values::Translation(values) | Values::Scale(values) => values.len(),
+			Values::Rotation(values) => values.len(),
+			Values::MorphWeights(values) => values.len(),
+		};
+		let keyframes = self.times.len() * if self.interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+		
+		match &self.values {
+			_ if keyframes == 0 || values % keyframes != 0 => None,
+			Values::MorphWeights(_) => Some(values / keyframes),
+			_ if values == keyframes => Some(1),
+			_ => None,
+		}
+	}
+}
+
+/// Animation of a node hierarchy, like a glTF animation.
+#[derive(Debug, Clone)]
+pub struct Clip {
+	pub name: String,
+	pub channels: Vec<Channel>,
+	/// Transforms of animated nodes, used for properties the clip doesn't animate.
+	pub rest: HashMap<NodeId, Trs>,
+	pub duration: f32,
+}
+
+/// Plays a clip on the nodes it targets, sampling it every frame before rendering.
+pub struct ClipPlayer {
+	clip: Clip,
+	pub speed: f32,
+	pub looping: bool,
+	time: f32,
+}
+
+impl ClipPlayer {
+	pub fn new(clip: Clip, looping: bool) -> ClipPlayer {
+		ClipPlayer {
+			clip,
+			speed: 1.0,
+			looping,
+			time: 0.0,
+		}
+	}
+}
+
+impl Behaviour for ClipPlayer {
+	fn update(&mut self, _node: NodeId, scene: &mut Scene, frame: &Frame) {
+		let duration = self.clip.duration;
+		
+		self.time += frame.dt * self.speed;
+		self.time = if self.looping && duration > 0.0 {
+			self.time.rem_euclid(duration)
+		} else {
+			self.time.max(0.0).min(duration)
+		};
+		
+		let mut poses = self.clip.rest.clone();
+		
+		for channel in &self.clip.channels {
+			let pose = poses.get_mut(&channel.node);
+			
+			match (&channel.values, pose) {
+				(Values::Translation(values), Some(pose)) => pose.translation = sample(channel, values, 1, self.time, VectorSpace::lerp)[0],
+				(Values::Scale(values), Some(pose)) => pose.scale = sample(channel, values, 1, self.time, VectorSpace::lerp)[0],
+				(Values::Rotation(values), Some(pose)) => pose.rotation = sample(channel, values, 1, self.time, interpolate)[0].normalize(),
+				(Values::MorphWeights(values), _) => {
+					if let (Some(stride), Some(node)) = (channel.stride(), scene.get_mut(channel.node)) {
+						node.morph_weights = sample(channel, values, stride, self.time, |a: f32, b, amount| a + (b - a) * amount);
+					}
+				},
+				_ => {},
+			}
+		}
+		
+		for (node, pose) in poses {
+			if let Some(node) = scene.get_mut(node) {
+				node.transform = pose.into();
+			}
+		}
+	}
+}
+
+/// Samples `stride` values of a channel at given time. Time before the first or after the last keyframe holds its value.
+fn sample<T>(channel: &Channel, values: &[T], stride: usize, time: f32, lerp: impl Fn(T, T, f32) -> T) -> Vec<T>
+             where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+	let times = &channel.times;
+	let cubic = channel.interpolation == Interpolation::CubicSpline;
+	let value = |keyframe: usize, element: usize| if cubic { values[(keyframe * 3 + 1) * stride + element] } else { values[keyframe * stride + element] };
+	
+	let next = match times.iter().position(|&keyframe| keyframe > time) {
+		Some(0) => return (0..stride).map(|element| value(0, element)).collect(),
+		None => return (0..stride).map(|element| value(times.len() - 1, element)).collect(),
+		Some(next) => next,
+	};
+	
+	let previous = next - 1;
+	let delta = times[next] - times[previous];
+	let amount = (time - times[previous]) / delta;
+	
+	(0..stride).map(|element| match channel.interpolation {
+		           Interpolation::Step => value(previous, element),
+		           Interpolation::Linear => lerp(value(previous, element), value(next, element), amount),
+		           Interpolation::CubicSpline => {
+			           let out_tangent = values[(previous * 3 + 2) * stride + element] * delta;
+			           let in_tangent = values[next * 3 * stride + element] * delta;
+			           let (t, t2, t3) = (amount, amount * amount, amount * amount * amount);
+			
+			           value(previous, element) * (2.0 * t3 - 3.0 * t2 + 1.0)
+			           + out_tangent * (t3 - 2.0 * t2 + t)
+			           + value(next, element) * (-2.0 * t3 + 3.0 * t2)
+			           + in_tangent * (t3 - t2)
+		           },
+	           })
+	           .collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn channel(times: Vec<f32>, interpolation: Interpolation, values: Values) -> Channel {
+		Channel { node: Scene::new().root(), times, interpolation, values }
+	}
+	
+	fn sample_at(channel: &Channel, values: &[f32], time: f32) -> f32 {
+		sample(channel, values, 1, time, |a: f32, b, amount| a + (b - a) * amount)[0]
+	}
+	
+	#[test]
+	fn step() {
+		let values = [0.0, 10.0, 20.0];
+		let channel = channel(vec![0.0, 1.0, 2.0], Interpolation::Step, Values::MorphWeights(values.to_vec()));
+		
+		assert_eq!(sample_at(&channel, &values, -1.0), 0.0);
+		assert_eq!(sample_at(&channel, &values, 0.5), 0.0);
+		assert_eq!(sample_at(&channel, &values, 1.5), 10.0);
+		assert_eq!(sample_at(&channel, &values, 3.0), 20.0);
+	}
+	
+	#[test]
+	fn linear() {
+		let values = [0.0, 10.0, 20.0];
+		let channel = channel(vec![0.0, 1.0, 2.0], Interpolation::Linear, Values::MorphWeights(values.to_vec()));
+		
+		assert_eq!(sample_at(&channel, &values, -1.0), 0.0);
+		assert_eq!(sample_at(&channel, &values, 0.5), 5.0);
+		assert_eq!(sample_at(&channel, &values, 1.25), 12.5);
+		assert_eq!(sample_at(&channel, &values, 3.0), 20.0);
+	}
+	
+	#[test]
+	fn cubic_spline() {
+		// In-tangent, value and out-tangent of each keyframe, tangents outside of the range are never used
+		let values = [99.0, 0.0, 10.0, 10.0, 10.0, -99.0];
+		let channel = channel(vec![0.0, 1.0], Interpolation::CubicSpline, Values::MorphWeights(values.to_vec()));
+		
+		assert_eq!(sample_at(&channel, &values, -1.0), 0.0);
+		assert!((sample_at(&channel, &values, 0.25) - 2.5).abs() < 1e-5);
+		assert!((sample_at(&channel, &values, 0.5) - 5.0).abs() < 1e-5);
+		assert_eq!(sample_at(&channel, &values, 2.0), 10.0);
+		
+		// Flat tangents ease in and out
+		let values = [0.0, 0.0, 0.0, 0.0, 10.0, 0.0];
+		assert!((sample_at(&channel, &values, 0.5) - 5.0).abs() < 1e-5);
+		assert!(sample_at(&channel, &values, 0.25) < 2.5);
+	}
+	
+	#[test]
+	fn morph_weights() {
+		let values = [0.0, 100.0, 10.0, 200.0];
+		let channel = channel(vec![0.0, 1.0], Interpolation::Linear, Values::MorphWeights(values.to_vec()));
+		
+		assert_eq!(channel.stride(), Some(2));
+		assert_eq!(sample(&channel, &values, 2, 0.5, |a: f32, b, amount| a + (b - a) * amount), vec![5.0, 150.0]);
+	}
+	
+	#[test]
+	fn stride() {
+		let translations = |count: usize| Values::Translation(vec![Vector3::new(0.0, 0.0, 0.0); count]);
+		
+		assert_eq!(channel(vec![0.0, 1.0], Interpolation::Linear, translations(2)).stride(), Some(1));
+		assert_eq!(channel(vec![0.0, 1.0], Interpolation::Linear, translations(3)).stride(), None);
+		assert_eq!(channel(vec![0.0, 1.0], Interpolation::CubicSpline, translations(6)).stride(), Some(1));
+		assert_eq!(channel(vec![0.0, 1.0], Interpolation::CubicSpline, translations(2)).stride(), None);
+		assert_eq!(channel(vec![], Interpolation::Linear, translations(0)).stride(), None);
+		assert_eq!(channel(vec![], Interpolation::Linear, Values::MorphWeights(vec![])).stride(), None);
+		assert_eq!(channel(vec![0.0, 1.0], Interpolation::Step, Values::MorphWeights(vec![0.0; 5])).stride(), None);
+	}
+}
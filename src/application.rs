@@ -1,44 +1,85 @@
-use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use err_derive::Error;
-use openvr::{System, Compositor, RenderModels, Context, InitError, tracked_device_index, TrackedDeviceClass, render_models, TrackedControllerRole};
+use openvr::{System, Compositor, Context, InitError, tracked_device_index, TrackingUniverseOrigin};
 use openvr::compositor::CompositorError;
-use openvr::system::TrackedPropertyError;
-use image::{ImageError, DynamicImage, ImageBuffer};
+use openvr::system::Event;
+use image::ImageError;
 use obj::{load_obj, ObjError, TexturedVertex, Obj};
-use cgmath::{Matrix4, Vector2};
+use cgmath::{Matrix4, Vector3, Point3, Zero, EuclideanSpace};
 
 use crate::renderer::{Renderer, RendererCreationError, RenderError, model};
 use crate::renderer::model::{Model, ModelError, Vertex};
+use crate::renderer::lod::{LodModel, LodLevel, LodThreshold};
+use crate::config::Config;
+use crate::eye_rotation::{EyeRotationController, KeyframeError, load_keyframes};
+use crate::input::{Input, Action, Hand, InputBackend};
+use crate::input::haptics::Pulse;
+use crate::input::bindings::{Bindings, BindingsError};
+use crate::input::openvr_backend::OpenVRBackend;
+use crate::input::mock_backend::MockBackend;
+use crate::devices::{Devices, DeviceError};
+use crate::scene::{Scene, NodeId};
+use crate::scene::file::{SceneFile, SceneFileError};
+use crate::behaviour::{Behaviours, Frame, BehaviourError};
+use crate::script::Scripts;
+use crate::grab::{Grabbing, GrabEvent};
+use crate::locomotion::Locomotion;
+use crate::chaperone::Chaperone;
 use crate::openvr_vulkan::mat4;
 
+const RENDER_SCALE_STEP: f32 = 0.1;
+
 pub struct Application {
 	context: Context,
 	system: System,
 	compositor: Compositor,
-	render_models: RenderModels,
 	renderer: Renderer,
+	eye_rotation: EyeRotationController,
+	input: Input,
+	config: Config,
 }
 
 impl Application {
-	pub fn new(device: Option<usize>, debug: bool) -> Result<Application, ApplicationCreationError> {
+	pub fn new(config: Config) -> Result<Application, ApplicationCreationError> {
 		let context = unsafe { openvr::init(openvr::ApplicationType::Scene) }?;
 		let system = context.system()?;
 		let compositor = context.compositor()?;
-		let render_models = context.render_models()?;
 		
-		let renderer = Renderer::new(&system, context.compositor()?, device, debug)?;
+		let renderer = Renderer::new(&system, context.compositor()?, &config)?;
+		
+		let keyframes = match &config.eye_script {
+			Some(path) => load_keyframes(path)?,
+			None => Vec::new(),
+		};
+		let eye_rotation = EyeRotationController::new(&config, keyframes);
+		
+		let bindings = match &config.bindings {
+			Some(path) => Bindings::load(path)?,
+			None => Bindings::defaults(),
+		};
+		let backend: Box<dyn InputBackend> = if config.mock_input {
+			Box::new(MockBackend)
+		} else {
+			Box::new(OpenVRBackend::new(context.system()?))
+		};
+		let input = Input::new(backend, bindings);
 		
 		Ok(Application {
 			context,
 			system,
 			compositor,
-			render_models,
 			renderer,
+			eye_rotation,
+			input,
+			config,
 		})
 	}
 	
 	pub fn run(mut self) -> Result<(), ApplicationRunError> {
-		let mut scene = Vec::new();
+		let mut scene = Scene::new();
 		
 		{
 			let obj: Obj<TexturedVertex, u16> = load_obj(model::SCENE_OBJ)?;
@@ -46,58 +87,223 @@ impl Application {
 			let vertices: Vec<Vertex> = obj.vertices.iter().map(Into::into).collect();
 			let image = image::load_from_memory(model::SCENE_PNG)?;
 			
-			let model = Model::new(&vertices, &obj.indices, image, &self.renderer)?;
+			let mut levels = vec![LodLevel { model: Model::new(&vertices, &obj.indices, image.clone(), &self.renderer)?,
+			                                 threshold: LodThreshold::Distance(std::f32::INFINITY) }];
+			
+			for (path, threshold) in &self.config.scene_lods {
+				let obj: Obj<TexturedVertex, u16> = load_obj(BufReader::new(File::open(path)?))?;
+				let vertices: Vec<Vertex> = obj.vertices.iter().map(Into::into).collect();
+				
+				levels.last_mut().unwrap().threshold = *threshold;
+				levels.push(LodLevel { model: Model::new(&vertices, &obj.indices, image.clone(), &self.renderer)?,
+				                       threshold: LodThreshold::Distance(std::f32::INFINITY) });
+			}
+			
+			scene.add(scene.root(), "scene", Some(LodModel::from_levels(levels)), Matrix4::from_scale(0.035));
+		}
+		
+		let mut devices = Devices::new(&self.system, &self.context, &self.renderer, &self.config, &mut scene)?;
+		let mut behaviours = Behaviours::default();
+		let mut scripts = Scripts::new();
+		let mut grabbing = Grabbing::default();
+		let mut locomotion = Locomotion::new(&mut scene, &self.renderer, &self.config)?;
+		let mut chaperone = if self.config.chaperone {
+			Some(Chaperone::new(&self.context, &mut scene, devices.play_space(), &self.renderer)?)
+		} else {
+			None
+		};
+		
+		if let Some(path) = &self.config.scene {
+			let file = SceneFile::load(path)?;
+			file.instantiate(&mut scene, &mut behaviours, &self.renderer)?;
 			
-			scene.push((model, Matrix4::from_scale(0.035)));
+			for script in file.scripts {
+				scripts.add(script);
+			}
+		}
+		
+		for spec in &self.config.behaviours {
+			spec.attach(&scene, &mut behaviours)?;
 		}
 		
-		let mut devices: HashMap<u32, usize> = HashMap::new();
-		let mut eye_rotation = (Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0));
+		let mut last_frame = Instant::now();
 		
 		loop {
 			let poses = self.compositor.wait_get_poses()?;
+			let now = Instant::now();
+			let dt = now.duration_since(last_frame).as_secs_f32();
+			last_frame = now;
 			
-			for i in 0..poses.render.len() as u32 {
-				if self.system.tracked_device_class(i) != TrackedDeviceClass::Invalid
-				&& self.system.tracked_device_class(i) != TrackedDeviceClass::HMD {
-					if devices.contains_key(&i) {
-						scene[*devices.get(&i).unwrap()].1 = mat4(poses.render[i as usize].device_to_absolute_tracking());
-					} else if let Some(model) = self.render_models.load_render_model(&self.system.string_tracked_device_property(i, 1003)?)? {
-						if let Some(texture) = self.render_models.load_texture(model.diffuse_texture_id().unwrap())? {
-							let vertices: Vec<Vertex> = model.vertices().iter().map(Into::into).collect();
-							let indices = model.indices();
-							let size = texture.dimensions();
-							let image = DynamicImage::ImageRgba8(ImageBuffer::from_raw(size.0 as u32, size.1 as u32, texture.data().into()).unwrap());
-							
-							let model = Model::new(&vertices, indices, image, &self.renderer)?;
-							
-							devices.insert(i, scene.len());
-							scene.push((model, mat4(poses.render[i as usize].device_to_absolute_tracking())));
-							println!("Loaded {:?}", self.system.tracked_device_class(i));
-						} else { break }
-					} else { break }
+			while let Some((event, _)) = self.system.poll_next_event_with_pose(TrackingUniverseOrigin::Standing) {
+				devices.handle_event(&self.system, &event, &mut scene);
+				
+				if let Some(chaperone) = &mut chaperone {
+					match event.event {
+						Event::ChaperoneDataHasChanged | Event::ChaperoneUniverseHasChanged => chaperone.reload(&self.context, &mut scene, &self.renderer)?,
+						_ => {},
+					}
 				}
 			}
 			
-			if let Some(i) = self.system.tracked_device_index_for_controller_role(TrackedControllerRole::LeftHand) {
-				if let Some(state) = self.system.controller_state(i) {
-					eye_rotation.0 += Vector2::new(-state.axis[0].y, state.axis[0].x) / 100.0;
+			for index in devices.receive_models(&self.renderer, &mut scene)? {
+				if let (Some(hand), Some(pulse)) = (devices.get(index).and_then(|device| device.hand), self.config.haptic_load) {
+					self.input.haptic_pulse(hand, pulse);
 				}
 			}
-
-			if let Some(i) = self.system.tracked_device_index_for_controller_role(TrackedControllerRole::RightHand) {
-				if let Some(state) = self.system.controller_state(i) {
-					eye_rotation.1 += Vector2::new(-state.axis[0].y, state.axis[0].x) / 100.0;
-				}
+			
+			devices.update(&self.system, &poses.render[..], &mut scene);
+			
+			self.input.update();
+			self.handle_actions()?;
+			
+			if self.action(Action::ToggleLocomotion) {
+				locomotion.active = !locomotion.active;
+				println!("Sticks control: {}", if locomotion.active { "locomotion" } else { "eyes" });
 			}
 			
+			let sticks = if locomotion.active {
+				(Vector3::zero(), Vector3::zero())
+			} else {
+				(self.input.axis(Action::RotateLeftEye).extend(self.input.axis(Action::RollLeftEye).x),
+				 self.input.axis(Action::RotateRightEye).extend(self.input.axis(Action::RollRightEye).x))
+			};
+			
+			let mut eye_rotation = self.eye_rotation.update(sticks, dt);
+			
 			let pose = poses.render[tracked_device_index::HMD as usize].device_to_absolute_tracking();
 			
-			self.renderer.render(pose, eye_rotation, &mut scene)?;
+			locomotion.update(&mut scene, &self.input, devices.play_space(), devices.hand_node(Hand::Right), mat4(pose), dt);
+			let play_space = scene.world_transform(devices.play_space());
+			
+			let frame = Frame { dt, hmd: play_space * mat4(pose), input: &self.input };
+			behaviours.update(&mut scene, &frame);
+			
+			let updated = eye_rotation;
+			if let Some(mode) = scripts.update(&mut scene, &frame, &mut eye_rotation) {
+				self.eye_rotation.set_mode(mode);
+				println!("Eye rotation mode: {}", mode);
+			}
+			
+			// Rotation set by scripts is kept within the same limits as stick input
+			if eye_rotation != updated {
+				eye_rotation = self.eye_rotation.set_rotation(eye_rotation);
+			}
+			
+			let hit_limit = self.eye_rotation.hit_limit();
+			if hit_limit.0 { self.haptic_pulse(Hand::Left, self.config.haptic_limit); }
+			if hit_limit.1 { self.haptic_pulse(Hand::Right, self.config.haptic_limit); }
+			
+			let hands = [devices.hand_node(Hand::Left), devices.hand_node(Hand::Right)];
+			for event in grabbing.update(&mut scene, &self.input, hands) {
+				match event {
+					GrabEvent::Grabbed(hand, _) => self.haptic_pulse(hand, self.config.haptic_action),
+					GrabEvent::Released(node) => self.save_grabbed(&scene, node),
+				}
+			}
+			
+			if let Some(chaperone) = &chaperone {
+				let trackers: Vec<Point3<f32>> = hands.iter()
+				                                      .filter_map(|&hand| scene.get(hand).filter(|node| node.visible))
+				                                      .map(|node| node.transform)
+				                                      .chain(Some(mat4(pose)))
+				                                      .map(|transform| Point3::from_vec(transform.w.truncate()))
+				                                      .collect();
+				chaperone.update(&mut scene, &trackers);
+			}
+			
+			self.renderer.render(pose, scene.world_transform(devices.play_space()), eye_rotation, &scene)?;
 		}
 		
 		// Ok(())
 	}
+	
+	fn handle_actions(&mut self) -> Result<(), ApplicationRunError> {
+		if self.action(Action::RenderScaleDown) {
+			let scale = self.renderer.set_render_scale(self.renderer.render_scale() - RENDER_SCALE_STEP)?;
+			println!("Render scale: {:.2}", scale);
+		}
+		if self.action(Action::RenderScaleUp) {
+			let scale = self.renderer.set_render_scale(self.renderer.render_scale() + RENDER_SCALE_STEP)?;
+			println!("Render scale: {:.2}", scale);
+		}
+		if self.action(Action::ToggleAdaptiveResolution) {
+			self.toggle_adaptive_resolution();
+		}
+		
+		// Changing render scale both ways at once toggles adaptive resolution
+		if (self.input.pressed(Action::RenderScaleDown) || self.input.pressed(Action::RenderScaleUp))
+		&& self.input.held(Action::RenderScaleDown) && self.input.held(Action::RenderScaleUp) {
+			self.toggle_adaptive_resolution();
+		}
+		
+		if self.action(Action::PreviousMode) {
+			println!("Eye rotation mode: {}", self.eye_rotation.cycle_mode(false));
+		}
+		if self.action(Action::ToggleMode) {
+			println!("Eye rotation mode: {}", self.eye_rotation.cycle_mode(true));
+		}
+		if self.action(Action::ResetLeftEye) {
+			self.eye_rotation.reset(0);
+		}
+		if self.action(Action::ResetRightEye) {
+			self.eye_rotation.reset(1);
+		}
+		
+		// Resetting both eyes at once resets them immediately
+		if (self.input.pressed(Action::ResetLeftEye) || self.input.pressed(Action::ResetRightEye))
+		&& self.input.held(Action::ResetLeftEye) && self.input.held(Action::ResetRightEye) {
+			self.eye_rotation.panic();
+			println!("Eye rotation panic reset");
+		}
+		
+		if self.action(Action::Screenshot) {
+			let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+			let mode = self.config.screenshot_mode;
+			let path = PathBuf::from(format!("screenshot-{}.{}", timestamp, mode.image_extension()));
+			if let Err(err) = self.renderer.screenshot(&path, mode) {
+				eprintln!("Failed to take {}: {}", path.display(), err);
+			}
+		}
+		
+		Ok(())
+	}
+	
+	/// Whether a button action fired this frame. Confirms it with a haptic pulse.
+	fn action(&mut self, action: Action) -> bool {
+		match self.input.pressed_by(action) {
+			Some(hand) => {
+				self.haptic_pulse(hand, self.config.haptic_action);
+				true
+			},
+			None => false,
+		}
+	}
+	
+	fn haptic_pulse(&mut self, hand: Hand, pulse: Option<Pulse>) {
+		if let Some(pulse) = pulse {
+			self.input.haptic_pulse(hand, pulse);
+		}
+	}
+	
+	/// Writes the transform of a released node to the scene file, if enabled. Failures are only logged.
+	fn save_grabbed(&self, scene: &Scene, node: NodeId) {
+		let (path, node) = match (&self.config.scene, scene.get(node)) {
+			(Some(path), Some(node)) if self.config.save_scene => (path, node),
+			_ => return,
+		};
+		
+		match SceneFile::save_transform(path, &node.name, &node.transform) {
+			Ok(true) => println!("Saved {} to {}", node.name, path.display()),
+			Ok(false) => {},
+			Err(err) => eprintln!("Failed to save {} to {}: {}", node.name, path.display(), err),
+		}
+	}
+	
+	fn toggle_adaptive_resolution(&mut self) {
+		let adaptive = !self.renderer.adaptive_resolution();
+		self.renderer.set_adaptive_resolution(adaptive);
+		println!("Adaptive resolution: {}", if adaptive { "on" } else { "off" });
+	}
 }
 
 impl Drop for Application {
@@ -111,6 +317,8 @@ impl Drop for Application {
 pub enum ApplicationCreationError {
 	#[error(display = "{}", _0)] OpenVRInitError(#[error(source)] InitError),
 	#[error(display = "{}", _0)] RendererCreationError(#[error(source)] RendererCreationError),
+	#[error(display = "{}", _0)] KeyframeError(#[error(source)] KeyframeError),
+	#[error(display = "{}", _0)] BindingsError(#[error(source)] BindingsError),
 }
 
 #[derive(Debug, Error)]
@@ -119,7 +327,9 @@ pub enum ApplicationRunError {
 	#[error(display = "{}", _0)] ModelError(#[error(source)] ModelError),
 	#[error(display = "{}", _0)] CompositorError(#[error(source)] CompositorError),
 	#[error(display = "{}", _0)] RenderError(#[error(source)] RenderError),
-	#[error(display = "{}", _0)] TrackedPropertyError(#[error(source)] TrackedPropertyError),
-	#[error(display = "{}", _0)] RenderModelError(#[error(source)] render_models::Error),
+	#[error(display = "{}", _0)] DeviceError(#[error(source)] DeviceError),
+	#[error(display = "{}", _0)] BehaviourError(#[error(source)] BehaviourError),
+	#[error(display = "{}", _0)] SceneFileError(#[error(source)] SceneFileError),
 	#[error(display = "{}", _0)] ObjError(#[error(source)] ObjError),
+	#[error(display = "{}", _0)] IoError(#[error(source)] std::io::Error),
 }
@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::num::ParseFloatError;
+use err_derive::Error;
+use cgmath::{Matrix4, Vector3, Quaternion, Decomposed, Rad, InnerSpace, SquareMatrix, VectorSpace, Zero};
+
+use crate::scene::{Scene, NodeId};
+use crate::input::Input;
+use crate::eye_rotation::{interpolate, from_angles, radians, read_keyframe_rows};
+
+/// State of the current frame, available to behaviours.
+pub struct Frame<'a> {
+	/// Time since the previous frame in seconds.
+	pub dt: f32,
+	/// HMD pose in the scene's root space.
+	pub hmd: Matrix4<f32>,
+	pub input: &'a Input,
+}
+
+/// Logic attached to a scene node, run once per frame before rendering.
+pub trait Behaviour {
+	fn update(&mut self, node: NodeId, scene: &mut Scene, frame: &Frame);
+}
+
+/// Behaviours of scene nodes. Behaviours of removed nodes are dropped.
+#[derive(Default)]
+pub struct Behaviours {
+	entries: Vec<(NodeId, Box<dyn Behaviour>)>,
+}
+
+impl Behaviours {
+	pub fn add(&mut self, node: NodeId, behaviour: impl Behaviour + 'static) {
+		self.entries.push((node, Box::new(behaviour)));
+	}
+	
+	/// Runs behaviours in the order they were added.
+	pub fn update(&mut self, scene: &mut Scene, frame: &Frame) {
+		self.entries.retain(|(node, _)| scene.get(*node).is_some());
+		
+		for (node, behaviour) in self.entries.iter_mut() {
+			behaviour.update(*node, scene, frame);
+		}
+	}
+}
+
+/// Rotates the node around an axis in its local space.
+pub struct Spin {
+	pub axis: Vector3<f32>,
+	/// Radians per second.
+	pub speed: f32,
+}
+
+impl Behaviour for Spin {
+	fn update(&mut self, node: NodeId, scene: &mut Scene, frame: &Frame) {
+		let node = scene.get_mut(node).unwrap();
+		node.transform = node.transform * Matrix4::from_axis_angle(self.axis.normalize(), Rad(self.speed * frame.dt));
+	}
+}
+
+/// Keeps the node at an offset from another node, like a device's node, without being its child.
+pub struct Follow {
+	pub target: NodeId,
+	/// Transform relative to the target.
+	pub offset: Matrix4<f32>,
+}
+
+impl Behaviour for Follow {
+	fn update(&mut self, node: NodeId, scene: &mut Scene, _frame: &Frame) {
+		let target = match scene.get(self.target) {
+			Some(target) if target.visible => scene.world_transform(self.target),
+			_ => return,
+		};
+		
+		if let Some(parent_inverse) = parent_transform(scene, node).invert() {
+			scene.get_mut(node).unwrap().transform = parent_inverse * target * self.offset;
+		}
+	}
+}
+
+/// Turns the node's +Z axis towards the HMD, keeping its position, scale and the world's up direction.
+pub struct Billboard;
+
+impl Behaviour for Billboard {
+	fn update(&mut self, node: NodeId, scene: &mut Scene, frame: &Frame) {
+		let world = scene.world_transform(node);
+		let position = world.w.truncate();
+		let forward = frame.hmd.w.truncate() - position;
+		let right = Vector3::unit_y().cross(forward);
+		
+		// Looking straight up or down leaves the rotation undefined
+		if right.magnitude2() < std::f32::EPSILON {
+			return;
+		}
+		
+		let forward = forward.normalize();
+		let right = right.normalize();
+		let up = forward.cross(right);
+		
+		let billboard = Matrix4::from_cols((right * world.x.truncate().magnitude()).extend(0.0),
+		                                   (up * world.y.truncate().magnitude()).extend(0.0),
+		                                   (forward * world.z.truncate().magnitude()).extend(0.0),
+		                                   position.extend(1.0));
+		
+		if let Some(parent_inverse) = parent_transform(scene, node).invert() {
+			scene.get_mut(node).unwrap().transform = parent_inverse * billboard;
+		}
+	}
+}
+
+pub type Keyframe = (f32, Decomposed<Vector3<f32>, Quaternion<f32>>);
+
+/// Moves the node through keyframed transforms, given as (time in seconds, local transform), sorted by time.
+pub struct Animate {
+	pub keyframes: Vec<Keyframe>,
+	pub looping: bool,
+	time: f32,
+}
+
+impl Animate {
+	pub fn new(keyframes: Vec<Keyframe>, looping: bool) -> Animate {
+		Animate {
+			keyframes,
+			looping,
+			time: 0.0,
+		}
+	}
+}
+
+impl Behaviour for Animate {
+	fn update(&mut self, node: NodeId, scene: &mut Scene, frame: &Frame) {
+		let duration = match self.keyframes.last() {
+			Some((time, _)) => *time,
+			None => return,
+		};
+		
+		self.time += frame.dt;
+		if self.looping && duration > 0.0 {
+			self.time %= duration;
+		}
+		
+		let next = self.keyframes.iter().position(|(time, _)| *time > self.time);
+		let transform = match next {
+			Some(0) => self.keyframes[0].1,
+			Some(next) => {
+				let (start, from) = self.keyframes[next - 1];
+				let (end, to) = self.keyframes[next];
+				let amount = (self.time - start) / (end - start);
+				
+				Decomposed {
+					scale: from.scale + (to.scale - from.scale) * amount,
+					rot: interpolate(from.rot, to.rot, amount),
+					disp: from.disp.lerp(to.disp, amount),
+				}
+			},
+			None => self.keyframes[self.keyframes.len() - 1].1,
+		};
+		
+		scene.get_mut(node).unwrap().transform = transform.into();
+	}
+}
+
+/// Loads keyframes for `Animate`. Each non-empty line not starting with `#` holds `TIME X Y Z [PITCH YAW ROLL [SCALE]]`,
+/// time in seconds, position in meters and angles in degrees.
+pub fn load_keyframes(path: &Path) -> Result<Vec<Keyframe>, BehaviourError> {
+	read_keyframe_rows::<BehaviourError>(path)?
+		.into_iter()
+		.map(|(number, values)| {
+			if values.len() != 4 && values.len() != 7 && values.len() != 8 {
+				return Err(BehaviourError::InvalidKeyframe(number));
+			}
+			
+			let angles = values.get(4..7).map_or_else(Vector3::zero, |angles| Vector3::new(angles[0], angles[1], angles[2]));
+			
+			Ok((values[0], Decomposed {
+				scale: values.get(7).cloned().unwrap_or(1.0),
+				rot: from_angles(angles.map(radians)),
+				disp: Vector3::new(values[1], values[2], values[3]),
+			}))
+		})
+		.collect()
+}
+
+/// Behaviour given on the command line, attached to its node once the scene is set up.
+#[derive(Debug, Clone)]
+pub enum BehaviourSpec {
+	/// Speed in degrees per second.
+	Spin { node: String, axis: Vector3<f32>, speed: f32 },
+	/// Node's own transform is used as the offset from the target.
+	Follow { node: String, target: String },
+	Billboard { node: String },
+	Keyframes { node: String, path: PathBuf, looping: bool },
+}
+
+impl BehaviourSpec {
+	pub fn attach(&self, scene: &Scene, behaviours: &mut Behaviours) -> Result<(), BehaviourError> {
+		let find = |name: &str| scene.find(name).ok_or_else(|| BehaviourError::UnknownNode(name.to_string()));
+		
+		match self {
+			BehaviourSpec::Spin { node, axis, speed } => {
+				behaviours.add(find(node)?, Spin { axis: *axis, speed: radians(*speed) });
+			},
+			BehaviourSpec::Follow { node, target } => {
+				let node = find(node)?;
+				let offset = scene.get(node).unwrap().transform;
+				behaviours.add(node, Follow { target: find(target)?, offset });
+			},
+			BehaviourSpec::Billboard { node } => {
+				behaviours.add(find(node)?, Billboard);
+			},
+			BehaviourSpec::Keyframes { node, path, looping } => {
+				behaviours.add(find(node)?, Animate::new(load_keyframes(path)?, *looping));
+			},
+		}
+		
+		Ok(())
+	}
+}
+
+impl FromStr for BehaviourSpec {
+	type Err = String;
+	
+	/// Parses `NODE:spin:X:Y:Z:DEGREES/S`, `NODE:follow:TARGET`, `NODE:billboard` or `NODE:keyframes:FILE[:once]`.
+	fn from_str(s: &str) -> Result<BehaviourSpec, String> {
+		let invalid = || format!("Invalid behaviour: {}, expected NODE:spin:X:Y:Z:DEGREES/S, NODE:follow:TARGET, NODE:billboard or NODE:keyframes:FILE[:once]", s);
+		let parts: Vec<&str> = s.split(':').collect();
+		let node = parts[0].to_string();
+		
+		match parts[1..] {
+			["spin", x, y, z, speed] => {
+				let numbers = [x, y, z, speed].iter()
+				                              .map(|number| number.parse::<f32>())
+				                              .collect::<Result<Vec<_>, _>>()
+				                              .map_err(|_| invalid())?;
+				
+				let axis = Vector3::new(numbers[0], numbers[1], numbers[2]);
+				if axis.is_zero() {
+					return Err("Spin axis can't be zero".to_string());
+				}
+				
+				Ok(BehaviourSpec::Spin { node, axis, speed: numbers[3] })
+			},
+			["follow", target] => Ok(BehaviourSpec::Follow { node, target: target.to_string() }),
+			["billboard"] => Ok(BehaviourSpec::Billboard { node }),
+			["keyframes", path] => Ok(BehaviourSpec::Keyframes { node, path: PathBuf::from(path), looping: true }),
+			["keyframes", path, "once"] => Ok(BehaviourSpec::Keyframes { node, path: PathBuf::from(path), looping: false }),
+			_ => Err(invalid()),
+		}
+	}
+}
+
+/// World transform of the node's parent.
+pub fn parent_transform(scene: &Scene, node: NodeId) -> Matrix4<f32> {
+	scene.get(node)
+	     .and_then(|node| node.parent())
+	     .map_or_else(Matrix4::identity, |parent| scene.world_transform(parent))
+}
+
+#[derive(Debug, Error)]
+pub enum BehaviourError {
+	#[error(display = "Unknown scene node: {}", _0)] UnknownNode(String),
+	#[error(display = "Invalid keyframe on line {}, expected 4, 7 or 8 values", _0)] InvalidKeyframe(usize),
+	#[error(display = "{}", _0)] IoError(#[error(source)] std::io::Error),
+	#[error(display = "{}", _0)] ParseFloatError(#[error(source)] ParseFloatError),
+}
@@ -0,0 +1,239 @@
+//! Offline tool generating a level of detail chain for scene meshes.
+//!
+//! Meshes are simplified using vertex clustering and written as `<name>_lod<N>.obj` files, ready to be passed to the
+//! demo using `--lod`. Each level is switched to once the object covers less of the view than its screen size threshold,
+//! which starts at `--screen-size` and shrinks by `--ratio` per level along with the grid.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use getopts::Options;
+use obj::{load_obj, Obj, TexturedVertex};
+use cgmath::{Matrix4, Point3, Vector3, Transform, InnerSpace, Zero};
+
+#[derive(Default)]
+struct Mesh {
+	positions: Vec<[f32; 3]>,
+	uvs: Vec<[f32; 2]>,
+	indices: Vec<u32>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let args: Vec<String> = env::args().collect();
+	let program = args[0].clone();
+	let mut opts = Options::new();
+	
+	opts.optopt("l", "levels", "Number of levels to generate (default 3)", "NUMBER");
+	opts.optopt("g", "grid", "Clustering grid resolution of the first level (default 64)", "NUMBER");
+	opts.optopt("r", "ratio", "Grid resolution ratio between consecutive levels (default 0.5)", "FACTOR");
+	opts.optopt("s", "screen-size", "Screen size threshold of the first level in percent of the view height (default 25)", "PERCENT");
+	opts.optopt("o", "output", "Output directory (default: next to the source)", "DIR");
+	opts.optflag("h", "help", "Print this help menu");
+	
+	let matches = opts.parse(&args[1..])?;
+	
+	if matches.opt_present("h") || matches.free.len() != 1 {
+		print_usage(&program, opts);
+		return Ok(());
+	}
+	
+	let levels: u32 = matches.opt_get_default("l", 3)?;
+	let grid: f32 = matches.opt_get_default("g", 64.0)?;
+	let ratio: f32 = matches.opt_get_default("r", 0.5)?;
+	let screen_size: f32 = matches.opt_get_default("s", 25.0)?;
+	
+	let source = PathBuf::from(&matches.free[0]);
+	let output = matches.opt_str("o")
+	                    .map(PathBuf::from)
+	                    .or_else(|| source.parent().map(Path::to_path_buf))
+	                    .unwrap_or_default();
+	let stem = source.file_stem().ok_or("Source path has no file name")?.to_string_lossy().into_owned();
+	
+	let mesh = match source.extension().and_then(|ext| ext.to_str()) {
+		Some("obj") => load_obj_mesh(&source)?,
+		Some("gltf") | Some("glb") => load_gltf_mesh(&source)?,
+		_ => return Err(format!("Unsupported source format: {}", source.display()).into()),
+	};
+	
+	println!("{}: {} vertices, {} triangles", source.display(), mesh.positions.len(), mesh.indices.len() / 3);
+	
+	let mut lod_args = Vec::new();
+	
+	for level in 1..=levels {
+		let resolution = grid * ratio.powi(level as i32 - 1);
+		let simplified = simplify(&mesh, resolution);
+		let path = output.join(format!("{}_lod{}.obj", stem, level));
+		
+		write_obj(&simplified, &path)?;
+		
+		println!("{}: {} vertices, {} triangles", path.display(), simplified.positions.len(), simplified.indices.len() / 3);
+		lod_args.push(format!("--lod {}:{}%", path.display(), screen_size * ratio.powi(level as i32 - 1)));
+	}
+	
+	println!("Use with: {}", lod_args.join(" "));
+	
+	Ok(())
+}
+
+fn load_obj_mesh(path: &Path) -> Result<Mesh, Box<dyn Error>> {
+	let obj: Obj<TexturedVertex, u32> = load_obj(BufReader::new(File::open(path)?))?;
+	
+	Ok(Mesh {
+		positions: obj.vertices.iter().map(|vertex| vertex.position).collect(),
+		uvs: obj.vertices.iter().map(|vertex| [vertex.texture[0], vertex.texture[1]]).collect(),
+		indices: obj.indices,
+	})
+}
+
+fn load_gltf_mesh(path: &Path) -> Result<Mesh, Box<dyn Error>> {
+	let (document, buffers, _) = gltf::import(path)?;
+	let mut mesh = Mesh::default();
+	
+	let scene = document.default_scene()
+	                    .or_else(|| document.scenes().next())
+	                    .ok_or("glTF file contains no scenes")?;
+	
+	let mut stack: Vec<(gltf::Node, Matrix4<f32>)> = Vec::new();
+	
+	for node in scene.nodes() {
+		let transform = Matrix4::from(node.transform().matrix());
+		stack.push((node, transform));
+	}
+	
+	while let Some((node, transform)) = stack.pop() {
+		for child in node.children() {
+			let child_transform = transform * Matrix4::from(child.transform().matrix());
+			stack.push((child, child_transform));
+		}
+		
+		let node_mesh = match node.mesh() {
+			Some(node_mesh) => node_mesh,
+			None => continue,
+		};
+		
+		for primitive in node_mesh.primitives() {
+			let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+			let positions: Vec<[f32; 3]> = match reader.read_positions() {
+				Some(positions) => positions.collect(),
+				None => continue,
+			};
+			let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+				Some(uvs) => uvs.into_f32().map(|uv| [uv[0], 1.0 - uv[1]]).collect(),
+				None => vec![[0.0, 0.0]; positions.len()],
+			};
+			let base = mesh.positions.len() as u32;
+			
+			match reader.read_indices() {
+				Some(indices) => mesh.indices.extend(indices.into_u32().map(|index| base + index)),
+				None => mesh.indices.extend(base .. base + positions.len() as u32),
+			}
+			
+			mesh.positions.extend(positions.iter().map(|&pos| Into::<[f32; 3]>::into(transform.transform_point(Point3::from(pos)))));
+			mesh.uvs.extend(uvs);
+		}
+	}
+	
+	Ok(mesh)
+}
+
+/// Merges all vertices falling into the same grid cell (and similar texture coordinates, to keep seams intact).
+/// `resolution` is the number of cells along the longest side of the mesh's bounding box.
+fn simplify(mesh: &Mesh, resolution: f32) -> Mesh {
+	let resolution = resolution.max(1.0);
+	
+	let (min, max) = mesh.positions.iter().fold(([std::f32::INFINITY; 3], [std::f32::NEG_INFINITY; 3]), |(mut min, mut max), pos| {
+		for axis in 0..3 {
+			min[axis] = min[axis].min(pos[axis]);
+			max[axis] = max[axis].max(pos[axis]);
+		}
+		(min, max)
+	});
+	
+	let extent = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f32::max);
+	let cell = if extent > 0.0 { extent / resolution } else { 1.0 };
+	
+	let mut clusters: HashMap<[i32; 5], u32> = HashMap::new();
+	let mut sums: Vec<([f32; 3], [f32; 2], f32)> = Vec::new();
+	
+	let remap: Vec<u32> = mesh.positions.iter().zip(&mesh.uvs).map(|(pos, uv)| {
+		let key = [
+			((pos[0] - min[0]) / cell) as i32,
+			((pos[1] - min[1]) / cell) as i32,
+			((pos[2] - min[2]) / cell) as i32,
+			(uv[0] * resolution).floor() as i32,
+			(uv[1] * resolution).floor() as i32,
+		];
+		
+		let index = *clusters.entry(key).or_insert_with(|| {
+			sums.push(([0.0; 3], [0.0; 2], 0.0));
+			sums.len() as u32 - 1
+		});
+		
+		let sum = &mut sums[index as usize];
+		for axis in 0..3 { (sum.0)[axis] += pos[axis]; }
+		for axis in 0..2 { (sum.1)[axis] += uv[axis]; }
+		sum.2 += 1.0;
+		
+		index
+	}).collect();
+	
+	let indices = mesh.indices.chunks(3)
+	                          .map(|tri| [remap[tri[0] as usize], remap[tri[1] as usize], remap[tri[2] as usize]])
+	                          .filter(|tri| tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0])
+	                          .flat_map(|tri| tri.to_vec())
+	                          .collect();
+	
+	Mesh {
+		positions: sums.iter().map(|(pos, _, n)| [pos[0] / n, pos[1] / n, pos[2] / n]).collect(),
+		uvs: sums.iter().map(|(_, uv, n)| [uv[0] / n, uv[1] / n]).collect(),
+		indices,
+	}
+}
+
+fn write_obj(mesh: &Mesh, path: &Path) -> Result<(), Box<dyn Error>> {
+	let mut file = BufWriter::new(File::create(path)?);
+	
+	for pos in &mesh.positions {
+		writeln!(file, "v {} {} {}", pos[0], pos[1], pos[2])?;
+	}
+	
+	for uv in &mesh.uvs {
+		writeln!(file, "vt {} {}", uv[0], uv[1])?;
+	}
+	
+	for normal in vertex_normals(mesh) {
+		writeln!(file, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+	}
+	
+	for tri in mesh.indices.chunks(3) {
+		writeln!(file, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", tri[0] + 1, tri[1] + 1, tri[2] + 1)?;
+	}
+	
+	Ok(())
+}
+
+/// Smooth normals, averaged from the adjacent faces weighted by their area.
+fn vertex_normals(mesh: &Mesh) -> Vec<Vector3<f32>> {
+	let mut normals = vec![Vector3::zero(); mesh.positions.len()];
+	
+	for tri in mesh.indices.chunks(3) {
+		let corner = |i: usize| Vector3::from(mesh.positions[tri[i] as usize]);
+		let normal = (corner(1) - corner(0)).cross(corner(2) - corner(0));
+		
+		for &index in tri {
+			normals[index as usize] += normal;
+		}
+	}
+	
+	normals.into_iter()
+	       .map(|normal| if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::unit_y() })
+	       .collect()
+}
+
+fn print_usage(program: &str, opts: Options) {
+	let brief = format!("Usage: {} [options] SOURCE.obj|SOURCE.gltf", program);
+	print!("{}", opts.usage(&brief));
+}
@@ -0,0 +1,164 @@
+use cgmath::{Matrix4, Vector3, Point3, InnerSpace, SquareMatrix};
+use openvr::Context;
+
+use crate::scene::{Scene, NodeId};
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture, Vertex};
+use crate::renderer::lod::LodModel;
+use crate::openvr_vulkan::RawChaperoneSetup;
+
+/// Distance between grid lines in meters.
+const GRID_SPACING: f32 = 0.25;
+const LINE_WIDTH: f32 = 0.005;
+/// Height of walls raised from the play area when the runtime has no collision bounds.
+const WALL_HEIGHT: f32 = 2.4;
+/// Distance from a wall in meters at which it starts fading in, and at which it becomes fully opaque.
+const FADE_DISTANCE: (f32, f32) = (0.8, 0.2);
+const GRID_COLOR: [u8; 4] = [64, 200, 255, 255];
+
+struct Wall {
+	node: NodeId,
+	/// Corner and two perpendicular edges of the wall's rectangle.
+	origin: Point3<f32>,
+	edges: (Vector3<f32>, Vector3<f32>),
+}
+
+/// Grid showing the play area and collision bounds from the runtime's chaperone setup, placed in the `play_space` node.
+/// Each wall fades in as the headset or a controller approaches it, along with the play area outline on the floor.
+pub struct Chaperone {
+	bounds: NodeId,
+	floor: Option<NodeId>,
+	walls: Vec<Wall>,
+}
+
+impl Chaperone {
+	/// Adds hidden grid nodes of the current chaperone setup to the scene.
+	pub fn new(context: &Context, scene: &mut Scene, play_space: NodeId, renderer: &Renderer) -> Result<Chaperone, ModelError> {
+		let texture = Texture::from_color(GRID_COLOR, renderer)?;
+		let bounds = scene.add(play_space, "chaperone", None, Matrix4::identity());
+		scene.get_mut(bounds).unwrap().visible = false;
+		
+		let play_area = context.chaperone().ok().and_then(|chaperone| chaperone.get_play_area_rect());
+		let mut quads = unsafe { RawChaperoneSetup::load() }.map_or_else(Vec::new, |setup| setup.live_collision_bounds());
+		
+		if quads.is_empty() {
+			quads = play_area.as_ref().map_or_else(Vec::new, raised_walls);
+		}
+		
+		let floor = match play_area {
+			Some(rect) => Some(scene.add(bounds, "play_area", Some(outline_model(&rect, &texture, renderer)?), Matrix4::identity())),
+			None => None,
+		};
+		
+		let mut walls = Vec::new();
+		for (index, quad) in quads.iter().enumerate() {
+			let origin = Point3::from(quad[0]);
+			let edges = (Point3::from(quad[1]) - origin, Point3::from(quad[3]) - origin);
+			let node = scene.add(bounds, format!("wall{}", index), Some(grid_model(origin, edges, &texture, renderer)?), Matrix4::identity());
+			
+			walls.push(Wall { node, origin, edges });
+		}
+		
+		Ok(Chaperone { bounds, floor, walls })
+	}
+	
+	/// Rebuilds the grid after the user changed the chaperone setup.
+	pub fn reload(&mut self, context: &Context, scene: &mut Scene, renderer: &Renderer) -> Result<(), ModelError> {
+		let play_space = match scene.get(self.bounds).and_then(|node| node.parent()) {
+			Some(play_space) => play_space,
+			None => return Ok(()),
+		};
+		
+		scene.remove(self.bounds);
+		*self = Chaperone::new(context, scene, play_space, renderer)?;
+		Ok(())
+	}
+	
+	/// Fades the walls by distance of the closest tracked point, given in the tracking space.
+	pub fn update(&self, scene: &mut Scene, trackers: &[Point3<f32>]) {
+		let mut strongest = 0.0f32;
+		
+		for wall in &self.walls {
+			let distance = trackers.iter()
+			                       .map(|&point| wall.distance(point))
+			                       .fold(std::f32::INFINITY, f32::min);
+			let opacity = ((FADE_DISTANCE.0 - distance) / (FADE_DISTANCE.0 - FADE_DISTANCE.1)).max(0.0).min(1.0);
+			
+			scene.get_mut(wall.node).unwrap().opacity = opacity;
+			strongest = strongest.max(opacity);
+		}
+		
+		if let Some(floor) = self.floor {
+			scene.get_mut(floor).unwrap().opacity = strongest;
+		}
+		
+		scene.get_mut(self.bounds).unwrap().visible = strongest > 0.0;
+	}
+}
+
+impl Wall {
+	fn distance(&self, point: Point3<f32>) -> f32 {
+		let offset = point - self.origin;
+		let along = |edge: Vector3<f32>| (offset.dot(edge) / edge.magnitude2().max(std::f32::EPSILON)).max(0.0).min(1.0) * edge;
+		
+		(self.origin + along(self.edges.0) + along(self.edges.1) - point).magnitude()
+	}
+}
+
+/// Grid lines covering the rectangle spanned by two perpendicular edges from its corner.
+fn grid_model(origin: Point3<f32>, edges: (Vector3<f32>, Vector3<f32>), texture: &Texture, renderer: &Renderer) -> Result<LodModel, ModelError> {
+	let mut vertices = Vec::new();
+	let mut indices = Vec::new();
+	
+	for &(along, across) in &[(edges.0, edges.1), (edges.1, edges.0)] {
+		if across.magnitude2() <= std::f32::EPSILON {
+			continue;
+		}
+		
+		let lines = (across.magnitude() / GRID_SPACING).ceil().max(1.0) as usize;
+		let half_width = across.normalize_to(LINE_WIDTH / 2.0);
+		
+		for line in 0..=lines {
+			let start = origin + across * (line as f32 / lines as f32);
+			add_line(start, start + along, half_width, &mut vertices, &mut indices);
+		}
+	}
+	
+	Ok(LodModel::new(Model::with_texture(&vertices, &indices, texture.clone(), renderer)?))
+}
+
+/// Walls of `WALL_HEIGHT` standing on the edges of the play area.
+fn raised_walls(rect: &[[f32; 3]; 4]) -> Vec<[[f32; 3]; 4]> {
+	(0..4).map(|corner| (rect[corner], rect[(corner + 1) % 4]))
+	      .map(|(start, end)| [start, end, [end[0], end[1] + WALL_HEIGHT, end[2]], [start[0], start[1] + WALL_HEIGHT, start[2]]])
+	      .collect()
+}
+
+/// Lines along the edges of the play area, lying on the floor.
+fn outline_model(rect: &[[f32; 3]; 4], texture: &Texture, renderer: &Renderer) -> Result<LodModel, ModelError> {
+	let mut vertices = Vec::new();
+	let mut indices = Vec::new();
+	
+	for corner in 0..4 {
+		let (start, end) = (Point3::from(rect[corner]), Point3::from(rect[(corner + 1) % 4]));
+		let side = (end - start).cross(Vector3::unit_y());
+		
+		// Degenerate edges have no direction to widen the line in
+		if side.magnitude2() > std::f32::EPSILON {
+			add_line(start, end, side.normalize_to(LINE_WIDTH / 2.0), &mut vertices, &mut indices);
+		}
+	}
+	
+	Ok(LodModel::new(Model::with_texture(&vertices, &indices, texture.clone(), renderer)?))
+}
+
+/// Adds a flat strip from `start` to `end`, extending by `half_width` to both sides.
+fn add_line(start: Point3<f32>, end: Point3<f32>, half_width: Vector3<f32>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>) {
+	let first = vertices.len() as u16;
+	
+	for &point in &[start - half_width, start + half_width, end - half_width, end + half_width] {
+		vertices.push(Vertex::new(point.x, point.y, point.z, 0.5, 0.5));
+	}
+	
+	indices.extend([0, 1, 2, 1, 3, 2].iter().map(|index| first + index));
+}
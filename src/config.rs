@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::path::PathBuf;
+use getopts::{Options, Matches};
+
+use crate::eye_rotation::{RotationMode, ModeSettings, ResetStyle};
+use crate::input::haptics::Pulse;
+use crate::renderer::stereo::StereoMode;
+use crate::renderer::lod::LodThreshold;
+use crate::behaviour::BehaviourSpec;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+	pub device: Option<usize>,
+	pub debug: bool,
+	/// Coarser scene levels, each used once the previous level's threshold is no longer met.
+	pub scene_lods: Vec<(PathBuf, LodThreshold)>,
+	pub behaviours: Vec<BehaviourSpec>,
+	pub near: f32,
+	/// `None` places the far plane at infinity.
+	pub far: Option<f32>,
+	/// Use 32-bit float depth buffer with near plane at depth 1 and far plane at 0.
+	pub reversed_z: bool,
+	/// Render target size relative to the recommended one.
+	pub render_scale: f32,
+	pub adaptive_resolution: bool,
+	/// Half-size of the full resolution region, relative to half of the view. `None` disables foveated rendering.
+	pub foveation: Option<f32>,
+	/// Show the eye images in a desktop window. `None` opens no window.
+	pub mirror: Option<StereoMode>,
+	/// How screenshots combine the eye images.
+	pub screenshot_mode: StereoMode,
+	pub eye_mode: RotationMode,
+	pub eye_mode_settings: Vec<(RotationMode, ModeSettings)>,
+	/// Keyframes played back in scripted eye rotation mode.
+	pub eye_script: Option<PathBuf>,
+	pub eye_reset: ResetStyle,
+	/// Rate (1/s) at which eyes return to neutral when sticks are released. `None` keeps them where they are.
+	pub eye_spring: Option<f32>,
+	/// Maximum pitch and yaw of the eyes in degrees, regardless of mode.
+	pub eye_clamp: Option<(f32, f32)>,
+	/// File mapping controller inputs to actions, on top of defaults for each controller type.
+	pub bindings: Option<PathBuf>,
+	/// Read no real controllers and log haptic pulses instead.
+	pub mock_input: bool,
+	/// Haptic pulse played when an eye reaches its rotation limit.
+	pub haptic_limit: Option<Pulse>,
+	/// Haptic pulse played when a button action fires.
+	pub haptic_action: Option<Pulse>,
+	/// Haptic pulse played when a controller's model is loaded.
+	pub haptic_load: Option<Pulse>,
+	/// Directory caching render models fetched from OpenVR between runs.
+	pub model_cache: Option<PathBuf>,
+	/// Show the headset's own model, visible once the eyes leave it.
+	pub spectator: bool,
+	/// Nodes, behaviours and scripts added to the scene.
+	pub scene: Option<PathBuf>,
+	/// Write transforms of nodes released after grabbing back to the scene file.
+	pub save_scene: bool,
+	/// Start with sticks driving locomotion instead of the eyes.
+	pub locomotion: bool,
+	/// Smooth locomotion speed in meters per second.
+	pub move_speed: f32,
+	/// Snap turn angle in degrees, zero turns smoothly.
+	pub snap_turn: f32,
+	/// Show the chaperone bounds as a grid when getting close to them.
+	pub chaperone: bool,
+	/// Scales distance between the eyes.
+	pub ipd_scale: f32,
+	/// Additional displacement of the left and right eye in meters, in head space.
+	pub eye_offsets: ([f32; 3], [f32; 3]),
+	/// Limit divergence and speed of eye rotation.
+	pub comfort: bool,
+	/// Maximum angle between the eyes in degrees.
+	pub max_divergence: f32,
+	/// Maximum rotation speed of each eye in degrees per second.
+	pub max_eye_speed: f32,
+	/// Darken the periphery as the eyes diverge.
+	pub vignette: bool,
+}
+
+impl Config {
+	pub fn options() -> Options {
+		let mut opts = Options::new();
+		
+		opts.optopt("d", "device", "Select fallback device to use", "NUMBER");
+		opts.optmulti("", "lod", "Add coarser scene level of detail used from given distance on, or below given percentage of the view height", "OBJ:DISTANCE|OBJ:SIZE%");
+		opts.optmulti("", "behaviour", "Attach a behaviour to a scene node", "NODE:spin:X:Y:Z:DEGREES/S|NODE:follow:TARGET|NODE:billboard|NODE:keyframes:FILE[:once]");
+		opts.optopt("", "near", "Near clipping plane distance (default 0.1)", "METERS");
+		opts.optopt("", "far", "Far clipping plane distance, or inf for infinite far plane (default 1000.1)", "METERS");
+		opts.optflag("", "reversed-z", "Use 32-bit float depth buffer with reversed Z");
+		opts.optopt("s", "render-scale", "Render target size relative to the recommended one (default 1.0)", "FACTOR");
+		opts.optflag("", "adaptive-resolution", "Adjust render scale to keep GPU time within the frame budget");
+		opts.optopt("", "foveation", "Render only the center of each eye at full resolution, using given size of the center region (0-1)", "SIZE");
+		opts.optopt("", "mirror", "Show the eyes in a desktop window combined as left, right, parallel, cross-eye, anaglyph, interlaced or wiggle; Tab switches between them", "MODE");
+		opts.optopt("", "screenshot-mode", "Combine the eyes in screenshots like in the mirror window, wiggle is saved as GIF (default left)", "MODE");
+		opts.optopt("", "eye-mode", "Initial eye rotation mode: free, mirrored, locked, oscillating or scripted (default free)", "MODE");
+		opts.optmulti("", "eye-mode-settings", "Override settings of an eye rotation mode, angles in degrees", "MODE:SENSITIVITY:DEAD_ZONE:ACCELERATION:MAX_PITCH:MAX_YAW[:MAX_ROLL]");
+		opts.optopt("", "eye-script", "Keyframes for scripted eye rotation mode", "FILE");
+		opts.optopt("", "eye-reset", "How reset button returns eyes to neutral: snap or ease (default ease)", "STYLE");
+		opts.optopt("", "eye-spring", "Return eyes to neutral at given rate when sticks are released", "RATE");
+		opts.optopt("", "eye-clamp", "Limit pitch and yaw of the eyes to given angles in degrees", "PITCH:YAW");
+		opts.optopt("b", "bindings", "Load controller bindings from file", "FILE");
+		opts.optflag("", "mock-input", "Use mock controllers, logging haptic feedback");
+		opts.optopt("", "haptic-limit", "Haptic pulse when an eye reaches its rotation limit (default 20:0.5)", "MS:STRENGTH|off");
+		opts.optopt("", "haptic-action", "Haptic pulse when a button action fires (default 10:0.3)", "MS:STRENGTH|off");
+		opts.optopt("", "haptic-load", "Haptic pulse when a controller model is loaded (default 50:0.6)", "MS:STRENGTH|off");
+		opts.optflag("", "no-haptics", "Disable all haptic feedback");
+		opts.optopt("", "model-cache", "Cache controller render models in given directory", "DIR");
+		opts.optflag("", "spectator", "Show the headset model, hidden while the eyes are inside it unless moved out with --eye-offset");
+		opts.optopt("", "scene", "Load nodes, behaviours and scripts from a scene file", "FILE");
+		opts.optflag("", "save-scene", "Save transforms of grabbed nodes to the scene file when they're released");
+		opts.optflag("", "locomotion", "Start with sticks moving, turning and teleporting instead of rotating the eyes");
+		opts.optopt("", "move-speed", "Smooth locomotion speed (default 2.0)", "M/S");
+		opts.optopt("", "snap-turn", "Snap turn angle, or 0 to turn smoothly (default 30)", "DEGREES");
+		opts.optflag("", "no-chaperone", "Don't show the play area and collision bounds when approaching them");
+		opts.optopt("", "ipd-scale", "Scale distance between the eyes (default 1.0)", "FACTOR");
+		opts.optmulti("", "eye-offset", "Move left or right eye by given offset in meters", "left|right:X:Y:Z");
+		opts.optflag("", "no-comfort", "Disable eye rotation comfort limits and vignette");
+		opts.optopt("", "max-divergence", "Maximum angle between the eyes (default 30)", "DEGREES");
+		opts.optopt("", "max-eye-speed", "Maximum rotation speed of each eye (default 120)", "DEGREES/S");
+		opts.optflag("", "no-vignette", "Don't darken the view as the eyes diverge");
+		opts.optflag("", "debug", "Enable debugging layer and info");
+		opts.optflag("h", "help", "Print this help menu");
+		
+		opts
+	}
+	
+	pub fn from_matches(matches: &Matches) -> Result<Config, Box<dyn Error>> {
+		let scene_lods = matches.opt_strs("lod")
+		                        .iter()
+		                        .map(|arg| parse_lod(arg))
+		                        .collect::<Result<Vec<_>, _>>()?;
+		
+		let behaviours = matches.opt_strs("behaviour")
+		                        .iter()
+		                        .map(|arg| arg.parse())
+		                        .collect::<Result<Vec<_>, _>>()?;
+		
+		let eye_mode_settings = matches.opt_strs("eye-mode-settings")
+		                               .iter()
+		                               .map(|arg| ModeSettings::parse(arg))
+		                               .collect::<Result<Vec<_>, _>>()?;
+		
+		let eye_clamp = match matches.opt_str("eye-clamp") {
+			Some(arg) => Some(parse_clamp(&arg)?),
+			None => None,
+		};
+		
+		let mut eye_offsets = ([0.0; 3], [0.0; 3]);
+		for arg in matches.opt_strs("eye-offset") {
+			let (right, offset) = parse_eye_offset(&arg)?;
+			if right { eye_offsets.1 = offset } else { eye_offsets.0 = offset }
+		}
+		
+		let haptics = !matches.opt_present("no-haptics");
+		let haptic = |name: &str, default: Pulse| -> Result<Option<Pulse>, String> {
+			match matches.opt_str(name) {
+				_ if !haptics => Ok(None),
+				Some(arg) => Pulse::parse(&arg),
+				None => Ok(Some(default)),
+			}
+		};
+		
+		let far = match matches.opt_str("far") {
+			Some(ref far) if far == "inf" => None,
+			Some(far) => Some(far.parse()?),
+			None => Some(1000.1),
+		};
+		
+		Ok(Config {
+			device: matches.opt_get("d")?,
+			debug: matches.opt_present("debug"),
+			scene_lods,
+			behaviours,
+			near: matches.opt_get_default("near", 0.1)?,
+			far,
+			reversed_z: matches.opt_present("reversed-z"),
+			render_scale: matches.opt_get_default("s", 1.0)?,
+			adaptive_resolution: matches.opt_present("adaptive-resolution"),
+			foveation: matches.opt_get("foveation")?,
+			mirror: matches.opt_get("mirror")?,
+			screenshot_mode: matches.opt_get_default("screenshot-mode", StereoMode::Left)?,
+			eye_mode: matches.opt_get_default("eye-mode", RotationMode::Free)?,
+			eye_mode_settings,
+			eye_script: matches.opt_str("eye-script").map(PathBuf::from),
+			eye_reset: matches.opt_get_default("eye-reset", ResetStyle::Ease)?,
+			eye_spring: matches.opt_get("eye-spring")?,
+			eye_clamp,
+			bindings: matches.opt_str("b").map(PathBuf::from),
+			mock_input: matches.opt_present("mock-input"),
+			haptic_limit: haptic("haptic-limit", Pulse::new(20, 0.5))?,
+			haptic_action: haptic("haptic-action", Pulse::new(10, 0.3))?,
+			haptic_load: haptic("haptic-load", Pulse::new(50, 0.6))?,
+			model_cache: matches.opt_str("model-cache").map(PathBuf::from),
+			spectator: matches.opt_present("spectator"),
+			scene: matches.opt_str("scene").map(PathBuf::from),
+			save_scene: matches.opt_present("save-scene"),
+			locomotion: matches.opt_present("locomotion"),
+			move_speed: matches.opt_get_default("move-speed", 2.0)?,
+			snap_turn: matches.opt_get_default("snap-turn", 30.0)?,
+			chaperone: !matches.opt_present("no-chaperone"),
+			ipd_scale: matches.opt_get_default("ipd-scale", 1.0)?,
+			eye_offsets,
+			comfort: !matches.opt_present("no-comfort"),
+			max_divergence: matches.opt_get_default("max-divergence", 30.0)?,
+			max_eye_speed: matches.opt_get_default("max-eye-speed", 120.0)?,
+			vignette: !matches.opt_present("no-vignette"),
+		})
+	}
+}
+
+fn parse_lod(arg: &str) -> Result<(PathBuf, LodThreshold), Box<dyn Error>> {
+	let mut parts = arg.rsplitn(2, ':');
+	let threshold = parts.next().unwrap();
+	let path = parts.next().ok_or_else(|| format!("Invalid LOD specification: {}, expected OBJ:DISTANCE or OBJ:SIZE%", arg))?;
+	
+	Ok((PathBuf::from(path), threshold.parse()?))
+}
+
+fn parse_clamp(arg: &str) -> Result<(f32, f32), Box<dyn Error>> {
+	let mut parts = arg.splitn(2, ':');
+	let pitch = parts.next().unwrap().parse()?;
+	let yaw = parts.next().ok_or_else(|| format!("Invalid eye clamp: {}, expected PITCH:YAW", arg))?.parse()?;
+	
+	Ok((pitch, yaw))
+}
+
+fn parse_eye_offset(arg: &str) -> Result<(bool, [f32; 3]), Box<dyn Error>> {
+	let parts: Vec<&str> = arg.split(':').collect();
+	let right = match parts[0] {
+		"left" if parts.len() == 4 => false,
+		"right" if parts.len() == 4 => true,
+		_ => return Err(format!("Invalid eye offset: {}, expected left|right:X:Y:Z", arg).into()),
+	};
+	
+	Ok((right, [parts[1].parse()?, parts[2].parse()?, parts[3].parse()?]))
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture};
+use super::loader::{ComponentMesh, TextureKey};
+
+// Color of render models without a diffuse texture
+const FALLBACK_COLOR: [u8; 4] = [200, 200, 200, 255];
+
+/// Named render model component on the GPU. Unnamed for render models without components.
+pub type ComponentModel = (Option<CString>, Model);
+
+/// Render models and textures uploaded to the GPU, shared between devices using the same render model.
+#[derive(Default)]
+pub struct AssetCache {
+	models: HashMap<CString, Vec<ComponentModel>>,
+	textures: HashMap<TextureKey, Texture>,
+}
+
+impl AssetCache {
+	pub fn get(&self, name: &CStr) -> Option<&[ComponentModel]> {
+		self.models.get(name).map(Vec::as_slice)
+	}
+	
+	/// Uploads components of a render model, reusing textures which are already on the GPU.
+	pub fn insert(&mut self, name: CString, meshes: Vec<ComponentMesh>, renderer: &Renderer) -> Result<&[ComponentModel], ModelError> {
+		let mut components = Vec::with_capacity(meshes.len());
+		
+		for ComponentMesh { component, mesh } in meshes {
+			let texture = match self.textures.get(&mesh.texture) {
+				Some(texture) => texture.clone(),
+				None => {
+					let texture = match &mesh.image {
+						Some(image) => Texture::new(image, renderer)?,
+						None => Texture::from_color(FALLBACK_COLOR, renderer)?,
+					};
+					self.textures.insert(mesh.texture, texture.clone());
+					texture
+				},
+			};
+			
+			components.push((component, Model::with_texture(&mesh.vertices, &mesh.indices, texture, renderer)?));
+		}
+		
+		Ok(self.models.entry(name).or_insert(components).as_slice())
+	}
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ffi::{CStr, CString};
+use std::fs::{self, File};
+use std::io::{self, Read, Write, BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use openvr::render_models::TextureId;
+use image::{DynamicImage, ImageBuffer};
+
+use crate::renderer::model::Vertex;
+use super::loader::{Mesh, ComponentMesh, TextureKey};
+
+const MAGIC: &[u8; 8] = b"VKRMC\0\0\x01";
+
+/// File holding given render model in the cache directory.
+fn path(dir: &Path, name: &CStr) -> PathBuf {
+	let file: String = name.to_string_lossy()
+	                       .chars()
+	                       .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+	                       .collect();
+	
+	dir.join(format!("{}.rmcache", file))
+}
+
+/// Reads a render model stored by `store`. Returns `Ok(None)` if it isn't cached.
+pub fn load(dir: &Path, name: &CStr) -> io::Result<Option<Vec<ComponentMesh>>> {
+	let mut file = match File::open(path(dir, name)) {
+		Ok(file) => BufReader::new(file),
+		Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+		Err(err) => return Err(err),
+	};
+	
+	let mut magic = [0; 8];
+	file.read_exact(&mut magic)?;
+	
+	// Different names can map to the same file, so the full name is stored too
+	if &magic != MAGIC || read_bytes(&mut file)? != name.to_bytes() {
+		return Ok(None);
+	}
+	
+	let mut textures: HashMap<TextureId, Arc<DynamicImage>> = HashMap::new();
+	let count = read_u32(&mut file)?;
+	let mut meshes = Vec::new();
+	
+	for _ in 0..count {
+		let component = match read_u8(&mut file)? {
+			0 => None,
+			_ => Some(CString::new(read_bytes(&mut file)?).map_err(invalid_data)?),
+		};
+		
+		let vertices = read_bytes(&mut file)?.chunks_exact(20)
+		                                     .map(|chunk| {
+			                                     let value = |index: usize| f32::from_le_bytes(chunk[index * 4 .. index * 4 + 4].try_into().unwrap());
+			                                     Vertex::new(value(0), value(1), value(2), value(3), value(4))
+		                                     })
+		                                     .collect();
+		
+		let indices = read_bytes(&mut file)?.chunks_exact(2)
+		                                    .map(|chunk| u16::from_le_bytes(chunk.try_into().unwrap()))
+		                                    .collect();
+		
+		let texture_id = read_u32(&mut file)? as TextureId;
+		
+		// Texture is stored only with the first component using it, the fallback texture isn't stored
+		let image = if read_u8(&mut file)? != 0 {
+			let width = read_u32(&mut file)?;
+			let height = read_u32(&mut file)?;
+			let buffer = ImageBuffer::from_raw(width, height, read_bytes(&mut file)?).ok_or_else(|| invalid_data("Invalid texture size"))?;
+			let image = Arc::new(DynamicImage::ImageRgba8(buffer));
+			textures.insert(texture_id, image.clone());
+			Some(image)
+		} else if texture_id < 0 {
+			None
+		} else {
+			Some(textures.get(&texture_id).cloned().ok_or_else(|| invalid_data("Missing texture"))?)
+		};
+		
+		meshes.push(ComponentMesh {
+			component,
+			mesh: Mesh {
+				vertices,
+				indices,
+				texture: if texture_id < 0 { TextureKey::Fallback } else { TextureKey::Disk(name.to_owned(), texture_id) },
+				image,
+			},
+		});
+	}
+	
+	Ok(Some(meshes))
+}
+
+pub fn store(dir: &Path, name: &CStr, meshes: &[ComponentMesh]) -> io::Result<()> {
+	fs::create_dir_all(dir)?;
+	let mut file = BufWriter::new(File::create(path(dir, name))?);
+	
+	file.write_all(MAGIC)?;
+	write_bytes(&mut file, name.to_bytes())?;
+	file.write_all(&(meshes.len() as u32).to_le_bytes())?;
+	
+	let mut written = Vec::new();
+	
+	for ComponentMesh { component, mesh } in meshes {
+		match component {
+			Some(component) => {
+				file.write_all(&[1])?;
+				write_bytes(&mut file, component.to_bytes())?;
+			},
+			None => file.write_all(&[0])?,
+		}
+		
+		let vertices: Vec<u8> = mesh.vertices
+		                            .iter()
+		                            .flat_map(|vertex| {
+			                            let [x, y, z] = vertex.pos();
+			                            let [u, v] = vertex.uv();
+			                            vec![x, y, z, u, v]
+		                            })
+		                            .flat_map(|value| value.to_le_bytes().to_vec())
+		                            .collect();
+		write_bytes(&mut file, &vertices)?;
+		
+		let indices: Vec<u8> = mesh.indices.iter().flat_map(|index| index.to_le_bytes().to_vec()).collect();
+		write_bytes(&mut file, &indices)?;
+		
+		file.write_all(&(mesh.texture.id() as u32).to_le_bytes())?;
+		
+		match &mesh.image {
+			Some(image) if !written.contains(&mesh.texture.id()) => {
+				let image = image.to_rgba();
+				file.write_all(&[1])?;
+				file.write_all(&image.width().to_le_bytes())?;
+				file.write_all(&image.height().to_le_bytes())?;
+				write_bytes(&mut file, &image.into_raw())?;
+				written.push(mesh.texture.id());
+			},
+			_ => file.write_all(&[0])?,
+		}
+	}
+	
+	file.flush()
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+                   where E: Into<Box<dyn std::error::Error + Send + Sync>> {
+	io::Error::new(ErrorKind::InvalidData, error)
+}
+
+fn read_u8(file: &mut impl Read) -> io::Result<u8> {
+	let mut buffer = [0; 1];
+	file.read_exact(&mut buffer)?;
+	Ok(buffer[0])
+}
+
+fn read_u32(file: &mut impl Read) -> io::Result<u32> {
+	let mut buffer = [0; 4];
+	file.read_exact(&mut buffer)?;
+	Ok(u32::from_le_bytes(buffer))
+}
+
+/// Reads a length prefixed byte string.
+fn read_bytes(file: &mut impl Read) -> io::Result<Vec<u8>> {
+	let length = read_u32(file)? as u64;
+	let mut buffer = Vec::new();
+	
+	// Length isn't trusted for allocation, in case the file is truncated or corrupted
+	file.take(length).read_to_end(&mut buffer)?;
+	
+	if buffer.len() as u64 != length {
+		return Err(ErrorKind::UnexpectedEof.into());
+	}
+	
+	Ok(buffer)
+}
+
+fn write_bytes(file: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+	file.write_all(&(bytes.len() as u32).to_le_bytes())?;
+	file.write_all(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgba;
+	
+	fn mesh(component: Option<&str>, texture: TextureKey, image: Option<Arc<DynamicImage>>) -> ComponentMesh {
+		ComponentMesh {
+			component: component.map(|component| CString::new(component).unwrap()),
+			mesh: Mesh {
+				vertices: vec![Vertex::new(0.0, 1.0, 2.0, 0.25, 0.5), Vertex::new(-1.0, 0.5, 0.0, 1.0, 0.0), Vertex::new(3.0, 0.0, 1.0, 0.0, 1.0)],
+				indices: vec![0, 1, 2, 2, 1, 0],
+				texture,
+				image,
+			},
+		}
+	}
+	
+	#[test]
+	fn round_trip() {
+		let dir = std::env::temp_dir().join(format!("disk-cache-{}", std::process::id()));
+		let name = CString::new("controller.model").unwrap();
+		let image = Arc::new(DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 7, 255]))));
+		let meshes = vec![
+			mesh(Some("body"), TextureKey::OpenVR(5), Some(image.clone())),
+			mesh(Some("trigger"), TextureKey::OpenVR(5), Some(image.clone())),
+			mesh(None, TextureKey::Fallback, None),
+		];
+		
+		assert!(load(&dir, &name).unwrap().is_none());
+		store(&dir, &name, &meshes).unwrap();
+		let loaded = load(&dir, &name).unwrap().unwrap();
+		
+		// Different name mapping to the same file
+		assert!(load(&dir, &CString::new("controller_model").unwrap()).unwrap().is_none());
+		fs::remove_dir_all(&dir).unwrap();
+		
+		assert_eq!(loaded.len(), meshes.len());
+		for (loaded, stored) in loaded.iter().zip(&meshes) {
+			assert_eq!(loaded.component, stored.component);
+			assert_eq!(loaded.mesh.indices, stored.mesh.indices);
+			assert_eq!(loaded.mesh.vertices.iter().map(|vertex| (vertex.pos(), vertex.uv())).collect::<Vec<_>>(),
+			           stored.mesh.vertices.iter().map(|vertex| (vertex.pos(), vertex.uv())).collect::<Vec<_>>());
+		}
+		
+		assert_eq!(loaded[0].mesh.texture, TextureKey::Disk(name.clone(), 5));
+		assert_eq!(loaded[1].mesh.texture, TextureKey::Disk(name.clone(), 5));
+		assert_eq!(loaded[2].mesh.texture, TextureKey::Fallback);
+		
+		let texture = loaded[0].mesh.image.as_ref().unwrap();
+		assert!(Arc::ptr_eq(texture, loaded[1].mesh.image.as_ref().unwrap()));
+		assert_eq!(texture.to_rgba().into_raw(), image.to_rgba().into_raw());
+		assert!(loaded[2].mesh.image.is_none());
+	}
+}
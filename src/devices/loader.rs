@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use openvr::{RenderModels, render_models};
+use openvr::render_models::TextureId;
+use image::{DynamicImage, ImageBuffer};
+
+use crate::renderer::model::Vertex;
+use super::disk_cache;
+
+// Delay between polls of render models OpenVR is still loading
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Render model fetched from OpenVR, not yet uploaded to the GPU.
+pub struct Mesh {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u16>,
+	pub texture: TextureKey,
+	/// Shared between meshes of a render model using the same texture. `None` for the fallback texture.
+	pub image: Option<Arc<DynamicImage>>,
+}
+
+/// Identifies a texture across render models.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TextureKey {
+	/// Texture loaded from OpenVR during this session.
+	OpenVR(TextureId),
+	/// Texture read from the disk cache file of given render model. Ids don't persist between sessions,
+	/// so these are only shared within the file.
+	Disk(CString, TextureId),
+	/// Plain color used for render models without a texture.
+	Fallback,
+}
+
+impl TextureKey {
+	/// OpenVR texture id, negative for the fallback texture.
+	pub fn id(&self) -> TextureId {
+		match *self {
+			TextureKey::OpenVR(id) | TextureKey::Disk(_, id) => id,
+			TextureKey::Fallback => -1,
+		}
+	}
+}
+
+/// Mesh of a single render model component. Models without components are loaded as one mesh with no name.
+pub struct ComponentMesh {
+	pub component: Option<CString>,
+	pub mesh: Mesh,
+}
+
+pub struct LoadResult {
+	pub name: CString,
+	pub meshes: Result<Vec<ComponentMesh>, render_models::Error>,
+}
+
+/// Fetches OpenVR render models on a background thread. Models are read from and stored to `cache_dir` if given.
+pub struct RenderModelLoader {
+	requests: Option<Sender<CString>>,
+	results: Receiver<LoadResult>,
+	worker: Option<JoinHandle<()>>,
+}
+
+impl RenderModelLoader {
+	pub fn new(render_models: RenderModels, cache_dir: Option<PathBuf>) -> RenderModelLoader {
+		let (requests, requests_receiver) = mpsc::channel();
+		let (results_sender, results) = mpsc::channel();
+		
+		let worker = thread::spawn(move || worker(render_models, cache_dir, requests_receiver, results_sender));
+		
+		RenderModelLoader {
+			requests: Some(requests),
+			results,
+			worker: Some(worker),
+		}
+	}
+	
+	/// Queues loading of a render model. Requests for a model which is already being loaded are merged.
+	pub fn request(&self, name: CString) {
+		if let Some(requests) = &self.requests {
+			requests.send(name).ok();
+		}
+	}
+	
+	/// Models finished since the last call.
+	pub fn finished(&self) -> impl Iterator<Item = LoadResult> + '_ {
+		self.results.try_iter()
+	}
+}
+
+impl Drop for RenderModelLoader {
+	/// Waits for the worker to stop, so it doesn't call OpenVR after the context is shut down.
+	fn drop(&mut self) {
+		self.requests = None;
+		
+		if let Some(worker) = self.worker.take() {
+			worker.join().ok();
+		}
+	}
+}
+
+fn worker(render_models: RenderModels, cache_dir: Option<PathBuf>, requests: Receiver<CString>, results: Sender<LoadResult>) {
+	let mut pending: HashSet<CString> = HashSet::new();
+	
+	loop {
+		if pending.is_empty() {
+			match requests.recv() {
+				Ok(name) => { pending.insert(name); },
+				Err(_) => return,
+			}
+		}
+		
+		loop {
+			match requests.try_recv() {
+				Ok(name) => { pending.insert(name); },
+				Err(TryRecvError::Empty) => break,
+				Err(TryRecvError::Disconnected) => return,
+			}
+		}
+		
+		pending.retain(|name| {
+			let meshes = match cache_dir.as_ref().and_then(|dir| load_cached(dir, name)) {
+				Some(meshes) => Ok(meshes),
+				None => match fetch(&render_models, name) {
+					Ok(Some(meshes)) => {
+						if let Some(dir) = &cache_dir {
+							if let Err(err) = disk_cache::store(dir, name, &meshes) {
+								eprintln!("Failed to cache render model {:?}: {}", name, err);
+							}
+						}
+						Ok(meshes)
+					},
+					Ok(None) => return true,
+					Err(err) => Err(err),
+				},
+			};
+			
+			results.send(LoadResult { name: name.clone(), meshes }).ok();
+			false
+		});
+		
+		if !pending.is_empty() {
+			thread::sleep(POLL_INTERVAL);
+		}
+	}
+}
+
+fn load_cached(dir: &Path, name: &CString) -> Option<Vec<ComponentMesh>> {
+	match disk_cache::load(dir, name) {
+		Ok(meshes) => meshes,
+		Err(err) => {
+			eprintln!("Failed to read cached render model {:?}: {}", name, err);
+			None
+		},
+	}
+}
+
+/// Fetches all renderable components of a render model. Returns `None` while OpenVR is still loading any of them.
+fn fetch(render_models: &RenderModels, name: &CString) -> Result<Option<Vec<ComponentMesh>>, render_models::Error> {
+	let components: Vec<(Option<CString>, CString)> = if render_models.component_count(name) == 0 {
+		vec![(None, name.clone())]
+	} else {
+		// Components without a render model are only coordinate systems, like the tip of the controller
+		render_models.component_names(name)
+		             .filter_map(|component| {
+			             let model = render_models.component_render_model_name(name, &component)?;
+			             Some((Some(component), model))
+		             })
+		             .collect()
+	};
+	
+	let mut meshes = Vec::with_capacity(components.len());
+	let mut textures = HashMap::new();
+	
+	for (component, model) in components {
+		match fetch_mesh(render_models, &model, &mut textures)? {
+			Some(mesh) => meshes.push(ComponentMesh { component, mesh }),
+			None => return Ok(None),
+		}
+	}
+	
+	Ok(Some(meshes))
+}
+
+/// Returns `None` while OpenVR is still loading the model or its texture. Textures already in `textures` aren't loaded again.
+fn fetch_mesh(render_models: &RenderModels, name: &CString, textures: &mut HashMap<TextureId, Arc<DynamicImage>>) -> Result<Option<Mesh>, render_models::Error> {
+	let model = match render_models.load_render_model(name)? {
+		Some(model) => model,
+		None => return Ok(None),
+	};
+	
+	// Some models, like those of base stations or trackers, may come without a texture
+	let texture_id = match model.diffuse_texture_id() {
+		Some(texture_id) => texture_id,
+		None => return Ok(Some(Mesh {
+			vertices: model.vertices().iter().map(Into::into).collect(),
+			indices: model.indices().to_vec(),
+			texture: TextureKey::Fallback,
+			image: None,
+		})),
+	};
+	
+	let image = match textures.get(&texture_id) {
+		Some(image) => image.clone(),
+		None => {
+			let texture = match render_models.load_texture(texture_id)? {
+				Some(texture) => texture,
+				None => return Ok(None),
+			};
+			
+			let size = texture.dimensions();
+			let image = Arc::new(DynamicImage::ImageRgba8(ImageBuffer::from_raw(size.0 as u32, size.1 as u32, texture.data().into()).unwrap()));
+			textures.insert(texture_id, image.clone());
+			image
+		},
+	};
+	
+	Ok(Some(Mesh {
+		vertices: model.vertices().iter().map(Into::into).collect(),
+		indices: model.indices().to_vec(),
+		texture: TextureKey::OpenVR(texture_id),
+		image: Some(image),
+	}))
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem;
+use err_derive::Error;
+use openvr::{Context, InitError, System, RenderModels, ControllerState, ControllerAxis, TrackedDeviceClass, TrackedDeviceIndex, TrackedDevicePose, TrackedControllerRole, property};
+use openvr::render_models::ControllerMode;
+use openvr::system::Event;
+use openvr::system::event::EventInfo;
+use cgmath::{Matrix4, SquareMatrix};
+
+pub mod loader;
+pub mod cache;
+mod disk_cache;
+
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture, Vertex};
+use crate::renderer::lod::LodModel;
+use crate::input::Hand;
+use crate::config::Config;
+use crate::openvr_vulkan::mat4;
+use crate::scene::{Scene, NodeId};
+use loader::RenderModelLoader;
+use cache::{AssetCache, ComponentModel};
+
+// Half extents of the box shown while a device's render model is loading
+const PLACEHOLDER_SIZE: [f32; 3] = [0.025, 0.025, 0.06];
+const PLACEHOLDER_COLOR: [u8; 4] = [160, 160, 160, 255];
+
+pub struct TrackedDevice {
+	pub class: TrackedDeviceClass,
+	pub hand: Option<Hand>,
+	/// Scene node following the device's pose. Visible while the device is connected and tracked.
+	pub node: NodeId,
+	/// Child of `node` shown while the render model is loading.
+	placeholder: NodeId,
+	render_model: CString,
+	model: ModelState,
+	connected: bool,
+}
+
+enum ModelState {
+	/// Waiting for the loader to fetch the render model. Placeholder is shown meanwhile.
+	Loading,
+	Loaded(Vec<Component>),
+	/// Device has no usable render model, it's not retried until its render model changes.
+	Unavailable,
+}
+
+/// Renderable part of a device's render model, like a trigger or a button, moving with controller state.
+struct Component {
+	/// `None` for render models without components.
+	name: Option<CString>,
+	/// Child of the device node.
+	node: NodeId,
+}
+
+/// Tracks devices and their render models in the scene, following OpenVR device events. HMD is only tracked in spectator mode.
+/// Nodes named `left_hand` and `right_hand` follow the controllers currently holding these roles.
+/// Device nodes are children of the `play_space` node, which places the tracking space in the scene.
+pub struct Devices {
+	devices: HashMap<TrackedDeviceIndex, TrackedDevice>,
+	play_space: NodeId,
+	hands: [NodeId; 2],
+	loader: RenderModelLoader,
+	cache: AssetCache,
+	render_models: RenderModels,
+	placeholder: LodModel,
+	/// Devices whose model finished loading since the last `receive_models`.
+	loaded: Vec<TrackedDeviceIndex>,
+	spectator: bool,
+}
+
+impl Devices {
+	/// Registers devices already connected at startup.
+	pub fn new(system: &System, context: &Context, renderer: &Renderer, config: &Config, scene: &mut Scene) -> Result<Devices, DeviceError> {
+		let play_space = scene.add(scene.root(), "play_space", None, Matrix4::identity());
+		let mut devices = Devices {
+			devices: HashMap::new(),
+			play_space,
+			hands: [scene.add(play_space, "left_hand", None, Matrix4::identity()),
+			        scene.add(play_space, "right_hand", None, Matrix4::identity())],
+			loader: RenderModelLoader::new(context.render_models()?, config.model_cache.clone()),
+			cache: AssetCache::default(),
+			render_models: context.render_models()?,
+			placeholder: placeholder(renderer)?,
+			loaded: Vec::new(),
+			spectator: config.spectator,
+		};
+		
+		for index in 0 .. openvr::MAX_TRACKED_DEVICE_COUNT as TrackedDeviceIndex {
+			if system.is_tracked_device_connected(index) {
+				devices.activate(system, index, scene);
+			}
+		}
+		
+		Ok(devices)
+	}
+	
+	pub fn handle_event(&mut self, system: &System, info: &EventInfo, scene: &mut Scene) {
+		let index = info.tracked_device_index;
+		
+		match info.event {
+			Event::TrackedDeviceActivated => self.activate(system, index, scene),
+			Event::TrackedDeviceDeactivated => {
+				if let Some(device) = self.devices.get_mut(&index) {
+					device.connected = false;
+					println!("Disconnected {:?}", device.class);
+				}
+			},
+			Event::TrackedDeviceRoleChanged => {
+				// Roles are swapped between devices, so all of them have to be refreshed
+				for (&index, device) in self.devices.iter_mut() {
+					device.hand = hand(system, index);
+				}
+			},
+			Event::PropertyChanged(changed) if changed.property == property::RenderModelName_String => self.activate(system, index, scene),
+			Event::PropertyChanged(changed) if changed.property == property::ControllerRoleHint_Int32 => {
+				if let Some(device) = self.devices.get_mut(&index) {
+					device.hand = hand(system, index);
+				}
+			},
+			_ => {},
+		}
+	}
+	
+	/// Adds a device, or refreshes an already known one. Render model is reloaded only if it has changed.
+	fn activate(&mut self, system: &System, index: TrackedDeviceIndex, scene: &mut Scene) {
+		let class = system.tracked_device_class(index);
+		if class == TrackedDeviceClass::Invalid || (class == TrackedDeviceClass::HMD && !self.spectator) {
+			return;
+		}
+		
+		let render_model = system.string_tracked_device_property(index, property::RenderModelName_String)
+		                         .unwrap_or_default();
+		
+		let already_loading = self.devices.values().any(|device| match device.model {
+			ModelState::Loading => device.render_model == render_model,
+			_ => false,
+		});
+		
+		let placeholder = &self.placeholder;
+		let play_space = self.play_space;
+		let device = self.devices.entry(index).or_insert_with(|| {
+			let node = scene.add(play_space, format!("device_{}", index), None, Matrix4::identity());
+			
+			TrackedDevice {
+				class,
+				hand: None,
+				node,
+				placeholder: scene.add(node, "placeholder", Some(placeholder.clone()), Matrix4::identity()),
+				render_model: CString::default(),
+				model: ModelState::Unavailable,
+				connected: true,
+			}
+		});
+		
+		if device.render_model != render_model || device.class != class {
+			if let ModelState::Loaded(components) = &device.model {
+				for component in components {
+					scene.remove(component.node);
+				}
+			}
+			
+			device.model = if render_model.as_bytes().is_empty() {
+				ModelState::Unavailable
+			} else if let Some(components) = self.cache.get(&render_model) {
+				self.loaded.push(index);
+				println!("Loaded {:?}", class);
+				ModelState::Loaded(instantiate(components, class, device.node, scene))
+			} else {
+				// Identical devices share a single load
+				if !already_loading {
+					self.loader.request(render_model.clone());
+				}
+				ModelState::Loading
+			};
+			device.render_model = render_model;
+		}
+		
+		device.class = class;
+		device.hand = hand(system, index);
+		device.connected = true;
+	}
+	
+	/// Uploads render models fetched by the loader. Returns indices of devices whose model has finished loading.
+	pub fn receive_models(&mut self, renderer: &Renderer, scene: &mut Scene) -> Result<Vec<TrackedDeviceIndex>, DeviceError> {
+		for result in self.loader.finished() {
+			let components = match result.meshes {
+				Ok(meshes) => Some(self.cache.insert(result.name.clone(), meshes, renderer)?),
+				Err(err) => {
+					eprintln!("Failed to load render model {:?}: {}", result.name, err);
+					None
+				},
+			};
+			
+			// Render model of a device might have changed while the old one was loading
+			for (&index, device) in self.devices.iter_mut() {
+				if let ModelState::Loading = device.model {
+					if device.render_model != result.name {
+						continue;
+					}
+					
+					device.model = match components {
+						Some(components) => {
+							self.loaded.push(index);
+							println!("Loaded {:?}", device.class);
+							ModelState::Loaded(instantiate(components, device.class, device.node, scene))
+						},
+						None => ModelState::Unavailable,
+					};
+				}
+			}
+		}
+		
+		Ok(mem::replace(&mut self.loaded, Vec::new()))
+	}
+	
+	/// Updates device nodes from their poses and moves render model components according to controller state.
+	/// Devices whose model isn't on the GPU yet show the placeholder.
+	pub fn update(&mut self, system: &System, poses: &[TrackedDevicePose], scene: &mut Scene) {
+		for hand in &self.hands {
+			scene.get_mut(*hand).unwrap().visible = false;
+		}
+		
+		for (&index, device) in self.devices.iter_mut() {
+			let pose = poses.get(index as usize)
+			                .filter(|pose| device.connected && pose.pose_is_valid() && pose.device_is_connected())
+			                .map(|pose| mat4(pose.device_to_absolute_tracking()));
+			
+			let node = scene.get_mut(device.node).unwrap();
+			node.visible = pose.is_some();
+			node.transform = pose.unwrap_or_else(Matrix4::identity);
+			
+			if let (Some(hand), Some(pose)) = (device.hand, pose) {
+				let hand_node = scene.get_mut(self.hands[hand.index()]).unwrap();
+				hand_node.visible = true;
+				hand_node.transform = pose;
+			}
+			
+			let loaded = match &device.model {
+				ModelState::Loaded(components) => components.iter().all(|component| {
+					scene.get(component.node).and_then(|node| node.model.as_ref()).map_or(true, LodModel::loaded)
+				}),
+				_ => false,
+			};
+			
+			// Placeholder would cover the view of the HMD wearer
+			scene.get_mut(device.placeholder).unwrap().visible = match device.model {
+				ModelState::Loading | ModelState::Loaded(_) => !loaded && device.class != TrackedDeviceClass::HMD,
+				ModelState::Unavailable => false,
+			};
+			
+			let components = match &device.model {
+				ModelState::Loaded(components) => components,
+				_ => continue,
+			};
+			
+			// Devices without controller state, like trackers, still report their static components
+			let state = system.controller_state(index).unwrap_or_else(idle_state);
+			
+			for component in components {
+				let component_node = scene.get_mut(component.node).unwrap();
+				component_node.visible = loaded;
+				
+				let name = match &component.name {
+					Some(name) => name,
+					None => continue,
+				};
+				
+				if let Some(component_state) = self.render_models.component_state(&device.render_model, name, &state, &ControllerMode::default()) {
+					component_node.transform = mat4(&component_state.tracking_to_component_render_model);
+					component_node.visible = loaded && component_state.is_visible();
+				}
+			}
+		}
+	}
+	
+	pub fn get(&self, index: TrackedDeviceIndex) -> Option<&TrackedDevice> {
+		self.devices.get(&index)
+	}
+	
+	/// Node holding the devices, its transform places the tracking space in the scene.
+	pub fn play_space(&self) -> NodeId {
+		self.play_space
+	}
+	
+	/// Node following the controller in given hand.
+	pub fn hand_node(&self, hand: Hand) -> NodeId {
+		self.hands[hand.index()]
+	}
+}
+
+/// Adds nodes for a device's render model. HMD model is hidden from eyes inside of it.
+fn instantiate(components: &[ComponentModel], class: TrackedDeviceClass, parent: NodeId, scene: &mut Scene) -> Vec<Component> {
+	components.iter()
+	          .map(|(name, model)| {
+		          let model = match class {
+			          TrackedDeviceClass::HMD => LodModel::new(model.clone()).hidden_inside(),
+			          _ => LodModel::new(model.clone()),
+		          };
+		          let node_name = name.as_ref().map_or("model".into(), |name| name.to_string_lossy());
+		
+		          Component {
+			          name: name.clone(),
+			          node: scene.add(parent, node_name, Some(model), Matrix4::identity()),
+		          }
+	          })
+	          .collect()
+}
+
+fn hand(system: &System, index: TrackedDeviceIndex) -> Option<Hand> {
+	match system.get_controller_role_for_tracked_device_index(index)? {
+		TrackedControllerRole::LeftHand => Some(Hand::Left),
+		TrackedControllerRole::RightHand => Some(Hand::Right),
+	}
+}
+
+/// Controller state with no buttons pressed and all axes centered.
+fn idle_state() -> ControllerState {
+	ControllerState {
+		packet_num: 0,
+		button_pressed: 0,
+		button_touched: 0,
+		axis: [ControllerAxis { x: 0.0, y: 0.0 }; 5],
+	}
+}
+
+/// Flat colored box, shown in place of devices whose render model is loading.
+fn placeholder(renderer: &Renderer) -> Result<LodModel, ModelError> {
+	let [x, y, z] = PLACEHOLDER_SIZE;
+	let vertices: Vec<Vertex> = (0..8).map(|corner| Vertex::new(if corner & 1 == 0 { -x } else { x },
+	                                                          if corner & 2 == 0 { -y } else { y },
+	                                                          if corner & 4 == 0 { -z } else { z },
+	                                                          0.5, 0.5))
+	                                  .collect();
+	let indices = [
+		0, 2, 1, 1, 2, 3,
+		4, 5, 6, 5, 7, 6,
+		0, 1, 4, 1, 5, 4,
+		2, 6, 3, 3, 6, 7,
+		0, 4, 2, 2, 4, 6,
+		1, 3, 5, 3, 7, 5,
+	];
+	
+	Ok(LodModel::new(Model::with_texture(&vertices, &indices, Texture::from_color(PLACEHOLDER_COLOR, renderer)?, renderer)?))
+}
+
+#[derive(Debug, Error)]
+pub enum DeviceError {
+	#[error(display = "{}", _0)] OpenVRInitError(#[error(source)] InitError),
+	#[error(display = "{}", _0)] ModelError(#[error(source)] ModelError),
+}
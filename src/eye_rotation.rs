@@ -0,0 +1,548 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::num::ParseFloatError;
+use err_derive::Error;
+use cgmath::{Vector2, Vector3, Quaternion, InnerSpace, Rotation, Rotation3, Zero, One, Deg, Rad};
+
+use crate::config::Config;
+
+/// Orientation of the left and right eye, relative to looking straight ahead.
+pub type EyeRotation = (Quaternion<f32>, Quaternion<f32>);
+
+// Rate (1/s) at which an eye eases back to neutral after reset
+const RECENTER_RATE: f32 = 6.0;
+// Angle (radians) under which easing eye is considered centered
+const CENTERED: f32 = 0.001;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RotationMode {
+	/// Each stick rotates its own eye.
+	Free,
+	/// Both sticks drive a single rotation, with yaw mirrored between the eyes (vergence).
+	Mirrored,
+	/// Both sticks drive a single rotation shared by both eyes.
+	Locked,
+	/// Eyes swing in opposite directions on their own.
+	Oscillating,
+	/// Rotation is played back from keyframes loaded from a file.
+	Scripted,
+}
+
+impl RotationMode {
+	pub const ALL: [RotationMode; 5] = [RotationMode::Free, RotationMode::Mirrored, RotationMode::Locked, RotationMode::Oscillating, RotationMode::Scripted];
+	
+	fn index(self) -> usize {
+		RotationMode::ALL.iter().position(|&mode| mode == self).unwrap()
+	}
+	
+	fn name(self) -> &'static str {
+		match self {
+			RotationMode::Free => "free",
+			RotationMode::Mirrored => "mirrored",
+			RotationMode::Locked => "locked",
+			RotationMode::Oscillating => "oscillating",
+			RotationMode::Scripted => "scripted",
+		}
+	}
+}
+
+impl fmt::Display for RotationMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for RotationMode {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<RotationMode, String> {
+		RotationMode::ALL.iter()
+		                 .find(|mode| mode.name() == s)
+		                 .cloned()
+		                 .ok_or_else(|| format!("Unknown eye rotation mode: {}, expected one of free, mirrored, locked, oscillating, scripted", s))
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ModeSettings {
+	/// Rotation speed in radians per second at full stick deflection.
+	/// In oscillating mode it's the number of swings per second instead.
+	pub sensitivity: f32,
+	/// Fraction of stick deflection ignored around the center.
+	pub dead_zone: f32,
+	/// Speed gain per second the stick is held.
+	pub acceleration: f32,
+	/// Maximum pitch, yaw and roll in radians. In oscillating mode it's the swing amplitude.
+	pub limits: Vector3<f32>,
+}
+
+impl ModeSettings {
+	pub fn default_for(mode: RotationMode) -> ModeSettings {
+		let unlimited = Vector3::new(std::f32::INFINITY, std::f32::INFINITY, std::f32::INFINITY);
+		
+		match mode {
+			RotationMode::Free | RotationMode::Locked | RotationMode::Scripted =>
+				ModeSettings { sensitivity: 0.9, dead_zone: 0.1, acceleration: 0.0, limits: unlimited },
+			RotationMode::Mirrored =>
+				ModeSettings { sensitivity: 0.9, dead_zone: 0.1, acceleration: 0.0, limits: Vector3::new(radians(45.0), radians(45.0), radians(45.0)) },
+			RotationMode::Oscillating =>
+				ModeSettings { sensitivity: 0.25, dead_zone: 0.0, acceleration: 0.0, limits: Vector3::new(0.0, radians(30.0), 0.0) },
+		}
+	}
+	
+	/// Parses `MODE:SENSITIVITY:DEAD_ZONE:ACCELERATION:MAX_PITCH:MAX_YAW[:MAX_ROLL]`, angles in degrees.
+	pub fn parse(arg: &str) -> Result<(RotationMode, ModeSettings), String> {
+		let parts: Vec<&str> = arg.split(':').collect();
+		if parts.len() != 6 && parts.len() != 7 {
+			return Err(format!("Invalid eye mode settings: {}, expected MODE:SENSITIVITY:DEAD_ZONE:ACCELERATION:MAX_PITCH:MAX_YAW[:MAX_ROLL]", arg));
+		}
+		
+		let mode = parts[0].parse()?;
+		let numbers = parts[1..].iter()
+		                        .map(|part| part.parse::<f32>())
+		                        .collect::<Result<Vec<_>, _>>()
+		                        .map_err(|err| format!("Invalid eye mode settings: {}, {}", arg, err))?;
+		
+		Ok((mode, ModeSettings {
+			sensitivity: numbers[0],
+			dead_zone: numbers[1].max(0.0).min(0.99),
+			acceleration: numbers[2].max(0.0),
+			limits: Vector3::new(radians(numbers[3]), radians(numbers[4]), radians(numbers.get(5).cloned().unwrap_or(std::f32::INFINITY))),
+		}))
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetStyle {
+	Snap,
+	Ease,
+}
+
+impl FromStr for ResetStyle {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<ResetStyle, String> {
+		match s {
+			"snap" => Ok(ResetStyle::Snap),
+			"ease" => Ok(ResetStyle::Ease),
+			_ => Err(format!("Unknown eye reset style: {}, expected snap or ease", s)),
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+	pub time: f32,
+	pub rotation: EyeRotation,
+}
+
+/// Turns controller stick input into orientation of both eyes, according to the selected mode.
+pub struct EyeRotationController {
+	mode: RotationMode,
+	settings: [ModeSettings; 5],
+	keyframes: Vec<Keyframe>,
+	rotation: EyeRotation,
+	time: f32,
+	held: (f32, f32),
+	/// Rotation, limit state and time step of the last update, overrides are limited relative to them.
+	previous: EyeRotation,
+	was_at_limit: (bool, bool),
+	dt: f32,
+	at_limit: (bool, bool),
+	hit_limit: (bool, bool),
+	returning: (bool, bool),
+	reset_style: ResetStyle,
+	spring: Option<f32>,
+	clamp: Vector3<f32>,
+	/// Maximum divergence between the eyes and turning speed in radians (per second), if comfort limits are enabled.
+	comfort: Option<(f32, f32)>,
+}
+
+impl EyeRotationController {
+	pub fn new(config: &Config, keyframes: Vec<Keyframe>) -> EyeRotationController {
+		let mut settings = [
+			ModeSettings::default_for(RotationMode::Free),
+			ModeSettings::default_for(RotationMode::Mirrored),
+			ModeSettings::default_for(RotationMode::Locked),
+			ModeSettings::default_for(RotationMode::Oscillating),
+			ModeSettings::default_for(RotationMode::Scripted),
+		];
+		
+		for (mode, mode_settings) in &config.eye_mode_settings {
+			settings[mode.index()] = *mode_settings;
+		}
+		
+		let clamp = config.eye_clamp.unwrap_or((std::f32::INFINITY, std::f32::INFINITY));
+		
+		EyeRotationController {
+			mode: config.eye_mode,
+			settings,
+			keyframes,
+			rotation: (Quaternion::one(), Quaternion::one()),
+			time: 0.0,
+			held: (0.0, 0.0),
+			previous: (Quaternion::one(), Quaternion::one()),
+			was_at_limit: (false, false),
+			dt: 0.0,
+			at_limit: (false, false),
+			hit_limit: (false, false),
+			returning: (false, false),
+			reset_style: config.eye_reset,
+			spring: config.eye_spring,
+			clamp: Vector3::new(radians(clamp.0), radians(clamp.1), std::f32::INFINITY),
+			comfort: if config.comfort { Some((radians(config.max_divergence), radians(config.max_eye_speed))) } else { None },
+		}
+	}
+	
+	pub fn set_mode(&mut self, mode: RotationMode) {
+		if mode == RotationMode::Mirrored || mode == RotationMode::Locked {
+			// Start from the average, so the eyes don't jump when they get linked
+			let right = if mode == RotationMode::Mirrored { mirror(self.rotation.1) } else { self.rotation.1 };
+			let average = interpolate(self.rotation.0, right, 0.5);
+			self.rotation = match mode {
+				RotationMode::Mirrored => (average, mirror(average)),
+				_ => (average, average),
+			};
+		}
+		
+		self.mode = mode;
+		self.time = 0.0;
+	}
+	
+	/// Switches to the next (or previous) mode. Scripted mode is skipped if there are no keyframes.
+	pub fn cycle_mode(&mut self, forward: bool) -> RotationMode {
+		let count = RotationMode::ALL.len();
+		let mut index = self.mode.index();
+		
+		loop {
+			index = if forward { (index + 1) % count } else { (index + count - 1) % count };
+			let mode = RotationMode::ALL[index];
+			if mode != RotationMode::Scripted || !self.keyframes.is_empty() {
+				self.set_mode(mode);
+				return mode;
+			}
+		}
+	}
+	
+	/// Brings the eye of given hand (0 left, 1 right) back to neutral. Eyes linked by the current mode are reset together.
+	/// Automatic modes restart from the beginning instead.
+	pub fn reset(&mut self, hand: usize) {
+		let linked = match self.mode {
+			RotationMode::Free => false,
+			RotationMode::Mirrored | RotationMode::Locked => true,
+			RotationMode::Oscillating | RotationMode::Scripted => {
+				self.time = 0.0;
+				return;
+			},
+		};
+		
+		match self.reset_style {
+			ResetStyle::Snap => {
+				if hand == 0 || linked { self.rotation.0 = Quaternion::one(); }
+				if hand == 1 || linked { self.rotation.1 = Quaternion::one(); }
+			},
+			ResetStyle::Ease => {
+				if hand == 0 || linked { self.returning.0 = true; }
+				if hand == 1 || linked { self.returning.1 = true; }
+			},
+		}
+	}
+	
+	/// Immediately brings both eyes to neutral, regardless of mode and reset style. Automatic modes are stopped.
+	pub fn panic(&mut self) {
+		if self.mode == RotationMode::Oscillating || self.mode == RotationMode::Scripted {
+			self.mode = RotationMode::Free;
+		}
+		
+		self.rotation = (Quaternion::one(), Quaternion::one());
+		self.returning = (false, false);
+		self.time = 0.0;
+	}
+	
+	/// Advances the orientation by `dt` seconds, given stick input of the left and right hand.
+	/// Input holds stick `x`, `y` and the roll axis in `z`.
+	pub fn update(&mut self, sticks: (Vector3<f32>, Vector3<f32>), dt: f32) -> EyeRotation {
+		let settings = self.settings[self.mode.index()];
+		self.previous = self.rotation;
+		self.was_at_limit = self.at_limit;
+		self.dt = dt;
+		self.time += dt;
+		
+		let left = self.stick_speed(sticks.0, 0, &settings, dt);
+		let right = self.stick_speed(sticks.1, 1, &settings, dt);
+		let return_rate = (self.return_rate(0, left), self.return_rate(1, right));
+		
+		let rotation = match self.mode {
+			RotationMode::Free => (
+				relax(self.rotation.0 * from_angles(left * dt), return_rate.0, dt),
+				relax(self.rotation.1 * from_angles(right * dt), return_rate.1, dt),
+			),
+			RotationMode::Mirrored => {
+				let rotation = relax(self.rotation.0 * from_angles((left + right) * dt), linked_rate(return_rate), dt);
+				(rotation, mirror(rotation))
+			},
+			RotationMode::Locked => {
+				let rotation = relax(self.rotation.0 * from_angles((left + right) * dt), linked_rate(return_rate), dt);
+				(rotation, rotation)
+			},
+			RotationMode::Oscillating => {
+				let phase = (self.time * settings.sensitivity * std::f32::consts::PI * 2.0).sin();
+				let rotation = from_angles(settings.limits * phase);
+				(rotation, mirror(rotation))
+			},
+			RotationMode::Scripted => sample_keyframes(&self.keyframes, self.time).unwrap_or(self.rotation),
+		};
+		
+		// Automatic modes swing into the limits on their own, only stick input is reported
+		let manual = self.mode != RotationMode::Oscillating && self.mode != RotationMode::Scripted;
+		self.limit(rotation, manual)
+	}
+	
+	/// Replaces rotation of the last update, e.g. with one set by scripts. It's kept within the same clamp and comfort limits.
+	pub fn set_rotation(&mut self, rotation: EyeRotation) -> EyeRotation {
+		self.limit(rotation, true)
+	}
+	
+	/// Applies comfort limits and clamps to the rotation reached during the last update and stores it.
+	fn limit(&mut self, rotation: EyeRotation, report: bool) -> EyeRotation {
+		let settings = self.settings[self.mode.index()];
+		let mut rotation = rotation;
+		
+		if let Some((_, max_speed)) = self.comfort {
+			rotation = (limit_speed(self.previous.0, rotation.0, max_speed * self.dt),
+			            limit_speed(self.previous.1, rotation.1, max_speed * self.dt));
+		}
+		
+		let unclamped = rotation;
+		
+		if self.mode != RotationMode::Oscillating {
+			rotation = (clamp(rotation.0, settings.limits), clamp(rotation.1, settings.limits));
+		}
+		
+		rotation = (clamp(rotation.0, self.clamp), clamp(rotation.1, self.clamp));
+		
+		// Limits are kept in the controller's own state, so turning back moves the eyes right away
+		if let Some((max_divergence, _)) = self.comfort {
+			rotation = limit_divergence(rotation, max_divergence);
+		}
+		
+		let at_limit = (report && rotation.0 != unclamped.0, report && rotation.1 != unclamped.1);
+		self.hit_limit = (at_limit.0 && !self.was_at_limit.0, at_limit.1 && !self.was_at_limit.1);
+		self.at_limit = at_limit;
+		
+		if rotation.0 == Quaternion::one() { self.returning.0 = false; }
+		if rotation.1 == Quaternion::one() { self.returning.1 = false; }
+		
+		self.rotation = rotation;
+		rotation
+	}
+	
+	/// Whether the left and right eye reached its rotation or comfort limit during the last update.
+	pub fn hit_limit(&self) -> (bool, bool) {
+		self.hit_limit
+	}
+	
+	/// Rate at which the eye of given hand returns to neutral. Moving the stick cancels pending reset,
+	/// releasing it lets the spring pull the eye back.
+	fn return_rate(&mut self, hand: usize, speed: Vector3<f32>) -> Option<f32> {
+		let returning = if hand == 0 { &mut self.returning.0 } else { &mut self.returning.1 };
+		
+		if !speed.is_zero() {
+			*returning = false;
+			None
+		} else if *returning {
+			Some(RECENTER_RATE)
+		} else {
+			self.spring
+		}
+	}
+	
+	/// Angular speed (pitch, yaw, roll) from a single hand. Stick Y pitches the eye, X turns it and the roll axis rolls it.
+	fn stick_speed(&mut self, stick: Vector3<f32>, hand: usize, settings: &ModeSettings, dt: f32) -> Vector3<f32> {
+		let held = if hand == 0 { &mut self.held.0 } else { &mut self.held.1 };
+		let direction = dead_zone(stick.truncate(), settings.dead_zone);
+		let roll = dead_zone(Vector2::new(stick.z, 0.0), settings.dead_zone).x;
+		
+		if direction.is_zero() && roll == 0.0 {
+			*held = 0.0;
+			return Vector3::zero();
+		}
+		
+		*held += dt;
+		
+		Vector3::new(-direction.y, direction.x, roll) * settings.sensitivity * (1.0 + settings.acceleration * *held)
+	}
+}
+
+/// Orientation from pitch (X), yaw (Y) and roll (Z) angles. Yaw is applied first, then pitch and roll around the view axis.
+pub fn from_angles(angles: Vector3<f32>) -> Quaternion<f32> {
+	Quaternion::from_angle_y(Rad(angles.y))
+	* Quaternion::from_angle_x(Rad(angles.x))
+	* Quaternion::from_angle_z(Rad(angles.z))
+}
+
+/// Inverse of `from_angles`.
+pub fn to_angles(rotation: Quaternion<f32>) -> Vector3<f32> {
+	let x = rotation.rotate_vector(Vector3::unit_x());
+	let y = rotation.rotate_vector(Vector3::unit_y());
+	let z = rotation.rotate_vector(Vector3::unit_z());
+	
+	Vector3::new((-z.y).max(-1.0).min(1.0).asin(),
+	             z.x.atan2(z.z),
+	             x.y.atan2(y.y))
+}
+
+/// Reflects orientation across the head's median plane, turning yaw and roll the other way.
+fn mirror(rotation: Quaternion<f32>) -> Quaternion<f32> {
+	Quaternion::new(rotation.s, rotation.v.x, -rotation.v.y, -rotation.v.z)
+}
+
+/// Shortest path interpolation between two orientations.
+pub fn interpolate(a: Quaternion<f32>, b: Quaternion<f32>, amount: f32) -> Quaternion<f32> {
+	let b = if a.dot(b) < 0.0 { -b } else { b };
+	a.slerp(b, amount).normalize()
+}
+
+fn dead_zone(stick: Vector2<f32>, dead_zone: f32) -> Vector2<f32> {
+	let length = stick.magnitude();
+	
+	if length <= dead_zone {
+		Vector2::zero()
+	} else {
+		stick / length * ((length.min(1.0) - dead_zone) / (1.0 - dead_zone))
+	}
+}
+
+fn linked_rate(rates: (Option<f32>, Option<f32>)) -> Option<f32> {
+	match rates {
+		(Some(left), Some(right)) => Some(left.max(right)),
+		_ => None,
+	}
+}
+
+/// Angle in radians of the rotation between two orientations.
+pub fn angle_between(a: Quaternion<f32>, b: Quaternion<f32>) -> f32 {
+	a.dot(b).abs().min(1.0).acos() * 2.0
+}
+
+/// Turns from `previous` towards `next` by at most `max_angle` radians.
+fn limit_speed(previous: Quaternion<f32>, next: Quaternion<f32>, max_angle: f32) -> Quaternion<f32> {
+	let angle = angle_between(previous, next);
+	
+	if angle > max_angle {
+		interpolate(previous, next, max_angle / angle)
+	} else {
+		next
+	}
+}
+
+/// Brings both eyes symmetrically closer together, so the angle between them is at most `max_divergence` radians.
+fn limit_divergence(rotation: EyeRotation, max_divergence: f32) -> EyeRotation {
+	let divergence = angle_between(rotation.0, rotation.1);
+	
+	if divergence > max_divergence {
+		let middle = interpolate(rotation.0, rotation.1, 0.5);
+		let amount = max_divergence / divergence;
+		(interpolate(middle, rotation.0, amount),
+		 interpolate(middle, rotation.1, amount))
+	} else {
+		rotation
+	}
+}
+
+fn relax(rotation: Quaternion<f32>, rate: Option<f32>, dt: f32) -> Quaternion<f32> {
+	match rate {
+		Some(rate) => {
+			let rotation = interpolate(rotation, Quaternion::one(), 1.0 - (-rate * dt).exp());
+			if angle_between(rotation, Quaternion::one()) < CENTERED { Quaternion::one() } else { rotation }
+		},
+		None => rotation,
+	}
+}
+
+fn clamp(rotation: Quaternion<f32>, limits: Vector3<f32>) -> Quaternion<f32> {
+	let angles = to_angles(rotation);
+	let clamped = Vector3::new(angles.x.max(-limits.x).min(limits.x),
+	                           angles.y.max(-limits.y).min(limits.y),
+	                           angles.z.max(-limits.z).min(limits.z));
+	
+	if clamped == angles { rotation } else { from_angles(clamped) }
+}
+
+pub fn radians(degrees: f32) -> f32 {
+	Rad::from(Deg(degrees)).0
+}
+
+fn sample_keyframes(keyframes: &[Keyframe], time: f32) -> Option<EyeRotation> {
+	let last = keyframes.last()?;
+	if keyframes.len() == 1 || last.time <= 0.0 {
+		return Some(last.rotation);
+	}
+	
+	let time = time % last.time;
+	let next = keyframes.iter().position(|keyframe| keyframe.time > time).unwrap_or(keyframes.len() - 1);
+	if next == 0 {
+		return Some(keyframes[0].rotation);
+	}
+	
+	let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+	let t = ((time - a.time) / (b.time - a.time)).max(0.0).min(1.0);
+	
+	Some((interpolate(a.rotation.0, b.rotation.0, t),
+	      interpolate(a.rotation.1, b.rotation.1, t)))
+}
+
+/// Loads keyframes for scripted mode. Each non-empty line not starting with `#` holds
+/// `TIME LEFT_PITCH LEFT_YAW RIGHT_PITCH RIGHT_YAW` or `TIME LEFT_PITCH LEFT_YAW LEFT_ROLL RIGHT_PITCH RIGHT_YAW RIGHT_ROLL`,
+/// time in seconds and angles in degrees.
+/// Playback loops once the last keyframe is reached.
+pub fn load_keyframes(path: &Path) -> Result<Vec<Keyframe>, KeyframeError> {
+	read_keyframe_rows::<KeyframeError>(path)?
+		.into_iter()
+		.map(|(number, values)| {
+			let (left, right) = match values.len() {
+				5 => (Vector3::new(values[1], values[2], 0.0), Vector3::new(values[3], values[4], 0.0)),
+				7 => (Vector3::new(values[1], values[2], values[3]), Vector3::new(values[4], values[5], values[6])),
+				_ => return Err(KeyframeError::InvalidLine(number)),
+			};
+			
+			Ok(Keyframe {
+				time: values[0],
+				rotation: (from_angles(left.map(radians)), from_angles(right.map(radians))),
+			})
+		})
+		.collect()
+}
+
+/// Reads rows of whitespace separated numbers from a keyframe file, with their line numbers, sorted by the first value (time).
+/// Empty lines and lines starting with `#` are skipped.
+pub fn read_keyframe_rows<E: From<std::io::Error> + From<ParseFloatError>>(path: &Path) -> Result<Vec<(usize, Vec<f32>)>, E> {
+	let source = fs::read_to_string(path)?;
+	let mut rows = Vec::new();
+	
+	for (number, line) in source.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		
+		let values = line.split_whitespace()
+		                 .map(str::parse::<f32>)
+		                 .collect::<Result<Vec<_>, _>>()?;
+		
+		rows.push((number + 1, values));
+	}
+	
+	rows.sort_by(|a, b| a.1[0].partial_cmp(&b.1[0]).unwrap_or(std::cmp::Ordering::Equal));
+	
+	Ok(rows)
+}
+
+#[derive(Debug, Error)]
+pub enum KeyframeError {
+	#[error(display = "Invalid keyframe on line {}, expected 5 or 7 values", _0)] InvalidLine(usize),
+	#[error(display = "{}", _0)] IoError(#[error(source)] std::io::Error),
+	#[error(display = "{}", _0)] ParseFloatError(#[error(source)] ParseFloatError),
+}
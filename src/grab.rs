@@ -0,0 +1,239 @@
+use cgmath::{Matrix4, Vector3, Point3, Quaternion, InnerSpace, SquareMatrix, EuclideanSpace, Transform, One};
+
+use crate::scene::{Scene, NodeId};
+use crate::renderer::model::Bounds;
+use crate::input::{Input, Action, Hand};
+use crate::behaviour::parent_transform;
+
+/// Distance in meters from the controller within which a node is grabbed directly.
+const GRAB_RADIUS: f32 = 0.05;
+/// Maximum distance in meters of nodes grabbed by pointing at them.
+const RAY_REACH: f32 = 10.0;
+/// Shortest distance between the hands used for two-handed scaling, so bringing them together doesn't collapse the node.
+const MIN_SPAN: f32 = 0.01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GrabEvent {
+	Grabbed(Hand, NodeId),
+	/// Node was let go by the last hand holding it.
+	Released(NodeId),
+}
+
+enum Hold {
+	/// Node's world transform relative to the hand.
+	OneHanded { hand: Hand, offset: Matrix4<f32> },
+	/// Center and span between the hands, and node's world transform when the second hand grabbed it.
+	TwoHanded { center: Point3<f32>, span: Vector3<f32>, start: Matrix4<f32> },
+}
+
+struct Grab {
+	node: NodeId,
+	hands: [bool; 2],
+	hold: Hold,
+}
+
+/// Picks up grabbable scene nodes with the grab actions, first within reach of the controller, then along its pointing ray.
+/// Node held with both hands is scaled by the distance between them and rotated with the line connecting them.
+#[derive(Default)]
+pub struct Grabbing {
+	grabs: Vec<Grab>,
+}
+
+impl Grabbing {
+	/// Moves grabbed nodes with the hands. `hands` are the nodes following the left and right controller.
+	pub fn update(&mut self, scene: &mut Scene, input: &Input, hands: [NodeId; 2]) -> Vec<GrabEvent> {
+		let mut events = Vec::new();
+		let poses = [hand_pose(scene, hands[0]), hand_pose(scene, hands[1])];
+		
+		for &hand in &Hand::ALL {
+			if poses[hand.index()].is_some() && input.held(grab_action(hand)) {
+				continue;
+			}
+			
+			let grab = match self.grabs.iter().position(|grab| grab.hands[hand.index()]) {
+				Some(grab) => grab,
+				None => continue,
+			};
+			
+			self.grabs[grab].hands[hand.index()] = false;
+			
+			if self.grabs[grab].hands == [false, false] {
+				events.push(GrabEvent::Released(self.grabs.remove(grab).node));
+			} else {
+				self.grabs[grab].hold = hold(scene, self.grabs[grab].node, self.grabs[grab].hands, &poses);
+			}
+		}
+		
+		for &hand in &Hand::ALL {
+			let pose = match poses[hand.index()] {
+				Some(pose) if input.pressed(grab_action(hand)) => pose,
+				_ => continue,
+			};
+			
+			if self.grabs.iter().any(|grab| grab.hands[hand.index()]) {
+				continue;
+			}
+			
+			let node = match pick(scene, &pose) {
+				Some(node) => node,
+				None => continue,
+			};
+			
+			let index = match self.grabs.iter().position(|grab| grab.node == node) {
+				Some(index) => index,
+				None => {
+					self.grabs.push(Grab { node, hands: [false, false], hold: Hold::OneHanded { hand, offset: Matrix4::identity() } });
+					self.grabs.len() - 1
+				},
+			};
+			
+			let grab = &mut self.grabs[index];
+			grab.hands[hand.index()] = true;
+			grab.hold = hold(scene, node, grab.hands, &poses);
+			events.push(GrabEvent::Grabbed(hand, node));
+		}
+		
+		self.grabs.retain(|grab| scene.get(grab.node).is_some());
+		
+		for grab in &self.grabs {
+			let world = match &grab.hold {
+				Hold::OneHanded { hand, offset } => match poses[hand.index()] {
+					Some(pose) => pose * offset,
+					None => continue,
+				},
+				Hold::TwoHanded { center, span, start } => match poses {
+					[Some(left), Some(right)] => {
+						let (new_center, new_span) = span_between(&left, &right);
+						let scale = new_span.magnitude().max(MIN_SPAN) / span.magnitude().max(MIN_SPAN);
+						let rotation = if span.magnitude() < MIN_SPAN || new_span.magnitude() < MIN_SPAN {
+							Quaternion::one()
+						} else {
+							Quaternion::from_arc(span.normalize(), new_span.normalize(), None)
+						};
+						
+						Matrix4::from_translation(new_center.to_vec())
+						* Matrix4::from(rotation)
+						* Matrix4::from_scale(scale)
+						* Matrix4::from_translation(-center.to_vec())
+						* start
+					},
+					_ => continue,
+				},
+			};
+			
+			if let Some(parent_inverse) = parent_transform(scene, grab.node).invert() {
+				scene.get_mut(grab.node).unwrap().transform = parent_inverse * world;
+			}
+		}
+		
+		events
+	}
+}
+
+fn grab_action(hand: Hand) -> Action {
+	match hand {
+		Hand::Left => Action::GrabLeft,
+		Hand::Right => Action::GrabRight,
+	}
+}
+
+/// World transform of a tracked hand.
+fn hand_pose(scene: &Scene, hand: NodeId) -> Option<Matrix4<f32>> {
+	match scene.get(hand) {
+		Some(node) if node.visible => Some(scene.world_transform(hand)),
+		_ => None,
+	}
+}
+
+fn hold(scene: &Scene, node: NodeId, hands: [bool; 2], poses: &[Option<Matrix4<f32>>; 2]) -> Hold {
+	let world = scene.world_transform(node);
+	
+	match (hands, poses) {
+		([true, true], [Some(left), Some(right)]) => {
+			let (center, span) = span_between(left, right);
+			Hold::TwoHanded { center, span, start: world }
+		},
+		_ => {
+			let hand = if hands[0] { Hand::Left } else { Hand::Right };
+			let offset = poses[hand.index()].and_then(|pose| pose.invert())
+			                                .map_or(world, |inverse| inverse * world);
+			Hold::OneHanded { hand, offset }
+		},
+	}
+}
+
+/// Center point and vector from the left to the right hand.
+fn span_between(left: &Matrix4<f32>, right: &Matrix4<f32>) -> (Point3<f32>, Vector3<f32>) {
+	let left = Point3::from_vec(left.w.truncate());
+	let right = Point3::from_vec(right.w.truncate());
+	
+	(left.midpoint(right), right - left)
+}
+
+/// Grabbable node within reach of the hand, or the closest one hit by its pointing ray.
+fn pick(scene: &Scene, pose: &Matrix4<f32>) -> Option<NodeId> {
+	let position = Point3::from_vec(pose.w.truncate());
+	let direction = -pose.z.truncate().normalize();
+	
+	let mut nearby: Option<(NodeId, f32)> = None;
+	let mut pointed: Option<(NodeId, f32)> = None;
+	
+	for id in scene.descendants(scene.root()) {
+		match scene.get(id) {
+			Some(node) if node.grabbable && scene.is_visible(id) => {},
+			_ => continue,
+		}
+		
+		let bounds = match subtree_bounds(scene, id, Matrix4::identity()) {
+			Some(bounds) => bounds,
+			None => continue,
+		};
+		
+		let world = scene.world_transform(id);
+		let inverse = match world.invert() {
+			Some(inverse) => inverse,
+			None => continue,
+		};
+		
+		let local = inverse.transform_point(position);
+		let closest = Point3::new(local.x.max(bounds.min.x).min(bounds.max.x),
+		                          local.y.max(bounds.min.y).min(bounds.max.y),
+		                          local.z.max(bounds.min.z).min(bounds.max.z));
+		let distance = (world.transform_point(closest) - position).magnitude();
+		
+		if distance <= GRAB_RADIUS && nearby.map_or(true, |(_, nearest)| distance < nearest) {
+			nearby = Some((id, distance));
+		}
+		
+		if let Some(distance) = bounds.intersect_ray(local, inverse.transform_vector(direction)) {
+			if distance <= RAY_REACH && pointed.map_or(true, |(_, nearest)| distance < nearest) {
+				pointed = Some((id, distance));
+			}
+		}
+	}
+	
+	nearby.or(pointed).map(|(id, _)| id)
+}
+
+/// Bounds of all models in the subtree, in the space given by `transform` relative to the node.
+fn subtree_bounds(scene: &Scene, id: NodeId, transform: Matrix4<f32>) -> Option<Bounds> {
+	let node = scene.get(id)?;
+	
+	let own = node.model.as_ref().map(|model| {
+		let bounds = model.levels()[0].model.bounds;
+		let corners = (0..8).map(|corner| transform.transform_point(Point3::new(if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+		                                                                         if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+		                                                                         if corner & 4 == 0 { bounds.min.z } else { bounds.max.z })));
+		
+		Bounds::from_points(corners)
+	});
+	
+	node.children()
+	    .iter()
+	    .filter_map(|&child| subtree_bounds(scene, child, transform * scene.get(child).unwrap().transform))
+	    .chain(own)
+	    .fold(None, |union: Option<Bounds>, bounds| Some(match union {
+		    Some(union) => union.union(&bounds),
+		    None => bounds,
+	    }))
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use err_derive::Error;
+use openvr::button_id;
+
+use super::{Action, Hand, Profile};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SourceKind {
+	/// OpenVR button id.
+	Button(u32),
+	/// Both components of an axis.
+	Axis(usize),
+	AxisX(usize),
+	AxisY(usize),
+}
+
+/// Single controller input bound to an action.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Source {
+	pub hand: Hand,
+	pub kind: SourceKind,
+}
+
+impl Source {
+	/// Parses `HAND.INPUT`, where input is `trigger`, `grip`, `menu`, `touchpad`, `a`, `system`, `buttonN`,
+	/// `axisN`, `axisN.x` or `axisN.y`.
+	pub fn parse(arg: &str) -> Result<Source, String> {
+		let invalid = || format!("Invalid input: {}, expected HAND.INPUT", arg);
+		
+		let mut parts = arg.splitn(2, '.');
+		let hand = match parts.next() {
+			Some("left") => Hand::Left,
+			Some("right") => Hand::Right,
+			_ => return Err(invalid()),
+		};
+		let input = parts.next().ok_or_else(invalid)?;
+		
+		let kind = match input {
+			"trigger" => SourceKind::Button(button_id::STEAM_VR_TRIGGER),
+			"grip" => SourceKind::Button(button_id::GRIP),
+			"menu" => SourceKind::Button(button_id::APPLICATION_MENU),
+			"touchpad" => SourceKind::Button(button_id::STEAM_VR_TOUCHPAD),
+			"a" => SourceKind::Button(button_id::A),
+			"system" => SourceKind::Button(button_id::SYSTEM),
+			_ if input.starts_with("button") => SourceKind::Button(input["button".len()..].parse().map_err(|_| invalid())?),
+			_ if input.starts_with("axis") => {
+				let mut axis = input["axis".len()..].splitn(2, '.');
+				let index: usize = axis.next().unwrap().parse().map_err(|_| invalid())?;
+				if index >= 5 {
+					return Err(invalid());
+				}
+				
+				match axis.next() {
+					None => SourceKind::Axis(index),
+					Some("x") => SourceKind::AxisX(index),
+					Some("y") => SourceKind::AxisY(index),
+					Some(_) => return Err(invalid()),
+				}
+			},
+			_ => return Err(invalid()),
+		};
+		
+		if let SourceKind::Button(button) = kind {
+			if button >= 64 {
+				return Err(invalid());
+			}
+		}
+		
+		Ok(Source { hand, kind })
+	}
+}
+
+type ActionMap = HashMap<Action, Vec<Source>>;
+
+/// Inputs bound to each action, for every controller profile.
+pub struct Bindings {
+	profiles: HashMap<Profile, ActionMap>,
+}
+
+impl Bindings {
+	pub fn defaults() -> Bindings {
+		let mut profiles = HashMap::new();
+		
+		for &profile in &Profile::ALL {
+			profiles.insert(profile, default_bindings(profile));
+		}
+		
+		Bindings { profiles }
+	}
+	
+	/// Loads bindings file on top of the defaults. Each line holds `ACTION = HAND.INPUT...`, replacing inputs bound to the action.
+	/// Lines following a `[PROFILE]` header apply only to that profile, lines before any header apply to all of them.
+	/// Empty lines and lines starting with `#` are ignored.
+	pub fn load(path: &Path) -> Result<Bindings, BindingsError> {
+		let source = fs::read_to_string(path)?;
+		let mut bindings = Bindings::defaults();
+		let mut section: Option<Profile> = None;
+		
+		for (number, line) in source.lines().enumerate() {
+			let line = line.trim();
+			let error = |message: String| BindingsError::InvalidLine(number + 1, message);
+			
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			
+			if line.starts_with('[') && line.ends_with(']') {
+				let name = &line[1 .. line.len() - 1];
+				section = Some(Profile::ALL.iter()
+				                           .find(|profile| profile.name() == name)
+				                           .cloned()
+				                           .ok_or_else(|| error(format!("Unknown profile: {}", name)))?);
+				continue;
+			}
+			
+			let mut parts = line.splitn(2, '=');
+			let action: Action = parts.next().unwrap().trim().parse().map_err(error)?;
+			let sources = parts.next()
+			                   .ok_or_else(|| error("Expected ACTION = HAND.INPUT...".to_string()))?
+			                   .split_whitespace()
+			                   .map(Source::parse)
+			                   .collect::<Result<Vec<_>, _>>()
+			                   .map_err(error)?;
+			
+			for (profile, actions) in bindings.profiles.iter_mut() {
+				if section.map_or(true, |section| section == *profile) {
+					actions.insert(action, sources.clone());
+				}
+			}
+		}
+		
+		Ok(bindings)
+	}
+	
+	pub fn sources(&self, profile: Profile, action: Action) -> &[Source] {
+		self.profiles
+		    .get(&profile)
+		    .and_then(|actions| actions.get(&action))
+		    .map_or(&[][..], Vec::as_slice)
+	}
+}
+
+fn default_bindings(profile: Profile) -> ActionMap {
+	let (stick, roll, mode_button, click) = match profile {
+		// Legacy input of Index controllers reports the trackpad on axis 0 and the thumbstick on axis 3
+		Profile::Index => ("axis3", Some("axis0.x"), "a", Some("touchpad")),
+		// Touch has no touchpad, X and A buttons switch modes and stick clicks are free
+		Profile::Touch => ("axis0", None, "a", Some("touchpad")),
+		Profile::Vive | Profile::Generic => ("axis0", None, "touchpad", None),
+	};
+	
+	let mut actions = ActionMap::new();
+	let mut bind = |action: Action, hand: &str, input: Option<&str>| {
+		let sources = input.map(|input| Source::parse(&format!("{}.{}", hand, input)).unwrap());
+		actions.insert(action, sources.into_iter().collect());
+	};
+	
+	bind(Action::RotateLeftEye, "left", Some(stick));
+	bind(Action::RotateRightEye, "right", Some(stick));
+	bind(Action::RollLeftEye, "left", roll);
+	bind(Action::RollRightEye, "right", roll);
+	bind(Action::ResetLeftEye, "left", Some("trigger"));
+	bind(Action::ResetRightEye, "right", Some("trigger"));
+	bind(Action::PreviousMode, "left", Some(mode_button));
+	bind(Action::ToggleMode, "right", Some(mode_button));
+	bind(Action::GrabLeft, "left", Some("grip"));
+	bind(Action::GrabRight, "right", Some("grip"));
+	// Sticks drive locomotion instead of the eyes while it's toggled on
+	bind(Action::ToggleLocomotion, "left", Some("menu"));
+	bind(Action::Move, "left", Some(stick));
+	bind(Action::Turn, "right", Some(&format!("{}.x", stick)));
+	bind(Action::Teleport, "right", Some(stick));
+	bind(Action::Screenshot, "right", Some("menu"));
+	// Vive touchpads switch modes, clicking both sticks elsewhere toggles adaptive resolution
+	bind(Action::RenderScaleDown, "left", click);
+	bind(Action::RenderScaleUp, "right", click);
+	bind(Action::ToggleAdaptiveResolution, "left", None);
+	
+	actions
+}
+
+#[derive(Debug, Error)]
+pub enum BindingsError {
+	#[error(display = "Invalid binding on line {}: {}", _0, _1)] InvalidLine(usize, String),
+	#[error(display = "{}", _0)] IoError(#[error(source)] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn source(hand: Hand, kind: SourceKind) -> Source {
+		Source { hand, kind }
+	}
+	
+	fn load(name: &str, source: &str) -> Result<Bindings, BindingsError> {
+		let path = std::env::temp_dir().join(format!("bindings-{}-{}.txt", std::process::id(), name));
+		fs::write(&path, source).unwrap();
+		let result = Bindings::load(&path);
+		fs::remove_file(&path).unwrap();
+		result
+	}
+	
+	fn invalid_line(source: &str) -> Option<usize> {
+		match load("invalid", source) {
+			Err(BindingsError::InvalidLine(line, _)) => Some(line),
+			_ => None,
+		}
+	}
+	
+	#[test]
+	fn parse_source() {
+		assert_eq!(Source::parse("left.trigger"), Ok(source(Hand::Left, SourceKind::Button(button_id::STEAM_VR_TRIGGER))));
+		assert_eq!(Source::parse("right.button7"), Ok(source(Hand::Right, SourceKind::Button(7))));
+		assert_eq!(Source::parse("left.axis2"), Ok(source(Hand::Left, SourceKind::Axis(2))));
+		assert_eq!(Source::parse("right.axis0.x"), Ok(source(Hand::Right, SourceKind::AxisX(0))));
+		assert_eq!(Source::parse("right.axis4.y"), Ok(source(Hand::Right, SourceKind::AxisY(4))));
+		
+		for invalid in &["trigger", "both.trigger", "left.", "left.pedal", "left.button64", "left.axis5", "left.axis0.z", "left.axisx"] {
+			assert!(Source::parse(invalid).is_err(), "{} should be rejected", invalid);
+		}
+	}
+	
+	#[test]
+	fn load_sections() {
+		let bindings = load("sections", "# all profiles\nscreenshot = left.menu right.button7\n\n[index]\nreset_left_eye = left.grip\n").unwrap();
+		let screenshot = [source(Hand::Left, SourceKind::Button(button_id::APPLICATION_MENU)), source(Hand::Right, SourceKind::Button(7))];
+		let defaults = Bindings::defaults();
+		
+		for &profile in &Profile::ALL {
+			assert_eq!(bindings.sources(profile, Action::Screenshot), screenshot);
+			assert_eq!(bindings.sources(profile, Action::ResetRightEye), defaults.sources(profile, Action::ResetRightEye));
+		}
+		
+		assert_eq!(bindings.sources(Profile::Index, Action::ResetLeftEye), [source(Hand::Left, SourceKind::Button(button_id::GRIP))]);
+		assert_eq!(bindings.sources(Profile::Vive, Action::ResetLeftEye), defaults.sources(Profile::Vive, Action::ResetLeftEye));
+	}
+	
+	#[test]
+	fn unbind() {
+		let bindings = load("unbind", "[vive]\nscreenshot =\n").unwrap();
+		
+		assert!(bindings.sources(Profile::Vive, Action::Screenshot).is_empty());
+		assert!(!bindings.sources(Profile::Index, Action::Screenshot).is_empty());
+	}
+	
+	#[test]
+	fn invalid_lines() {
+		assert_eq!(invalid_line("# comment\n\njump = left.trigger\n"), Some(3));
+		assert_eq!(invalid_line("screenshot = left.menu\nscreenshot left.menu\n"), Some(2));
+		assert_eq!(invalid_line("screenshot = left.pedal\n"), Some(1));
+		assert_eq!(invalid_line("[vive]\n[hydra]\n"), Some(2));
+		assert_eq!(invalid_line("[touch]\n# comment\n"), None);
+	}
+}
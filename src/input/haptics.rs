@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Controller vibration.
+#[derive(Debug, Copy, Clone)]
+pub struct Pulse {
+	pub duration: Duration,
+	/// Vibration strength from 0 to 1.
+	pub strength: f32,
+}
+
+impl Pulse {
+	pub fn new(milliseconds: u64, strength: f32) -> Pulse {
+		Pulse {
+			duration: Duration::from_millis(milliseconds),
+			strength: strength.max(0.0).min(1.0),
+		}
+	}
+	
+	/// Parses `MILLISECONDS:STRENGTH`, or `off` for no pulse.
+	pub fn parse(arg: &str) -> Result<Option<Pulse>, String> {
+		if arg == "off" {
+			return Ok(None);
+		}
+		
+		let invalid = || format!("Invalid haptic pulse: {}, expected MILLISECONDS:STRENGTH or off", arg);
+		let mut parts = arg.splitn(2, ':');
+		let milliseconds = parts.next().unwrap().parse().map_err(|_| invalid())?;
+		let strength = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+		
+		Ok(Some(Pulse::new(milliseconds, strength)))
+	}
+}
@@ -0,0 +1,19 @@
+use super::{InputBackend, Hand, Profile, ControllerInput};
+use super::haptics::Pulse;
+
+/// Backend with two idle controllers, logging haptic pulses instead of playing them.
+pub struct MockBackend;
+
+impl InputBackend for MockBackend {
+	fn profile(&self, _hand: Hand) -> Option<Profile> {
+		Some(Profile::Generic)
+	}
+	
+	fn state(&self, _hand: Hand) -> Option<ControllerInput> {
+		Some(ControllerInput::default())
+	}
+	
+	fn haptic_pulse(&mut self, hand: Hand, pulse: Pulse) {
+		println!("Haptic pulse {:?}: {} ms, strength {:.2}", hand, pulse.duration.as_millis(), pulse.strength);
+	}
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::str::FromStr;
+use cgmath::Vector2;
+
+pub mod bindings;
+pub mod openvr_backend;
+pub mod mock_backend;
+pub mod haptics;
+
+use bindings::{Bindings, SourceKind};
+use haptics::Pulse;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Hand {
+	Left,
+	Right,
+}
+
+impl Hand {
+	pub const ALL: [Hand; 2] = [Hand::Left, Hand::Right];
+	
+	pub fn index(self) -> usize {
+		match self {
+			Hand::Left => 0,
+			Hand::Right => 1,
+		}
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+	RotateLeftEye,
+	RotateRightEye,
+	RollLeftEye,
+	RollRightEye,
+	ResetLeftEye,
+	ResetRightEye,
+	ToggleMode,
+	PreviousMode,
+	RenderScaleUp,
+	RenderScaleDown,
+	ToggleAdaptiveResolution,
+	Screenshot,
+	GrabLeft,
+	GrabRight,
+	ToggleLocomotion,
+	Move,
+	Turn,
+	Teleport,
+}
+
+impl Action {
+	pub const ALL: [Action; 18] = [
+		Action::RotateLeftEye,
+		Action::RotateRightEye,
+		Action::RollLeftEye,
+		Action::RollRightEye,
+		Action::ResetLeftEye,
+		Action::ResetRightEye,
+		Action::ToggleMode,
+		Action::PreviousMode,
+		Action::RenderScaleUp,
+		Action::RenderScaleDown,
+		Action::ToggleAdaptiveResolution,
+		Action::Screenshot,
+		Action::GrabLeft,
+		Action::GrabRight,
+		Action::ToggleLocomotion,
+		Action::Move,
+		Action::Turn,
+		Action::Teleport,
+	];
+	
+	pub fn name(self) -> &'static str {
+		match self {
+			Action::RotateLeftEye => "rotate_left_eye",
+			Action::RotateRightEye => "rotate_right_eye",
+			Action::RollLeftEye => "roll_left_eye",
+			Action::RollRightEye => "roll_right_eye",
+			Action::ResetLeftEye => "reset_left_eye",
+			Action::ResetRightEye => "reset_right_eye",
+			Action::ToggleMode => "toggle_mode",
+			Action::PreviousMode => "previous_mode",
+			Action::RenderScaleUp => "render_scale_up",
+			Action::RenderScaleDown => "render_scale_down",
+			Action::ToggleAdaptiveResolution => "toggle_adaptive_resolution",
+			Action::Screenshot => "screenshot",
+			Action::GrabLeft => "grab_left",
+			Action::GrabRight => "grab_right",
+			Action::ToggleLocomotion => "toggle_locomotion",
+			Action::Move => "move",
+			Action::Turn => "turn",
+			Action::Teleport => "teleport",
+		}
+	}
+}
+
+impl fmt::Display for Action {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for Action {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<Action, String> {
+		Action::ALL.iter()
+		           .find(|action| action.name() == s)
+		           .cloned()
+		           .ok_or_else(|| format!("Unknown action: {}", s))
+	}
+}
+
+/// Controller type, selecting default bindings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Profile {
+	Vive,
+	Index,
+	Touch,
+	Generic,
+}
+
+impl Profile {
+	pub const ALL: [Profile; 4] = [Profile::Vive, Profile::Index, Profile::Touch, Profile::Generic];
+	
+	pub fn name(self) -> &'static str {
+		match self {
+			Profile::Vive => "vive",
+			Profile::Index => "index",
+			Profile::Touch => "touch",
+			Profile::Generic => "generic",
+		}
+	}
+}
+
+/// Raw state of a controller, independent of the VR runtime.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ControllerInput {
+	/// Bit mask of pressed buttons, indexed by OpenVR button id.
+	pub buttons: u64,
+	pub axes: [[f32; 2]; 5],
+}
+
+/// Source of controller state for the input system.
+pub trait InputBackend {
+	/// Profile of the controller held in given hand, `None` if there is none.
+	fn profile(&self, hand: Hand) -> Option<Profile>;
+	fn state(&self, hand: Hand) -> Option<ControllerInput>;
+	/// Starts vibrating the controller in given hand, replacing the current pulse.
+	fn haptic_pulse(&mut self, hand: Hand, pulse: Pulse);
+	/// Called once per frame, before the state is read.
+	fn update(&mut self) {}
+}
+
+// Axis deflection at which an axis bound to a digital action counts as pressed
+const AXIS_PRESS: f32 = 0.5;
+
+/// Maps controller state to named actions using the bindings of each controller's profile.
+pub struct Input {
+	backend: Box<dyn InputBackend>,
+	bindings: Bindings,
+	current: [Option<(Profile, ControllerInput)>; 2],
+	previous: [Option<(Profile, ControllerInput)>; 2],
+}
+
+impl Input {
+	pub fn new(backend: Box<dyn InputBackend>, bindings: Bindings) -> Input {
+		Input {
+			backend,
+			bindings,
+			current: [None, None],
+			previous: [None, None],
+		}
+	}
+	
+	/// Fetches new controller state. Should be called once per frame.
+	pub fn update(&mut self) {
+		self.backend.update();
+		self.previous = self.current;
+		
+		for &hand in &Hand::ALL {
+			self.current[hand.index()] = match (self.backend.profile(hand), self.backend.state(hand)) {
+				(Some(profile), Some(state)) => Some((profile, state)),
+				_ => None,
+			};
+		}
+	}
+	
+	pub fn haptic_pulse(&mut self, hand: Hand, pulse: Pulse) {
+		self.backend.haptic_pulse(hand, pulse);
+	}
+	
+	/// Value of an analog action. Values of all bound inputs are summed.
+	pub fn axis(&self, action: Action) -> Vector2<f32> {
+		self.sources(action)
+		    .fold(Vector2::new(0.0, 0.0), |sum, (_, kind, state)| sum + axis_value(kind, state))
+	}
+	
+	pub fn held(&self, action: Action) -> bool {
+		self.sources(action)
+		    .any(|(_, kind, state)| is_held(kind, state))
+	}
+	
+	/// Whether a digital action has been activated this frame.
+	pub fn pressed(&self, action: Action) -> bool {
+		self.pressed_by(action).is_some()
+	}
+	
+	/// Hand which activated a digital action this frame.
+	pub fn pressed_by(&self, action: Action) -> Option<Hand> {
+		self.sources(action)
+		    .find(|&(hand, kind, state)| {
+			    is_held(kind, state) && !self.previous[hand.index()].as_ref().map_or(false, |(_, previous)| is_held(kind, previous))
+		    })
+		    .map(|(hand, _, _)| hand)
+	}
+	
+	/// Inputs bound to the action, according to the profile of the controller they belong to, with current state of the controller.
+	fn sources<'a>(&'a self, action: Action) -> impl Iterator<Item = (Hand, SourceKind, &'a ControllerInput)> + 'a {
+		Hand::ALL.iter()
+		         .filter_map(move |&hand| self.current[hand.index()].as_ref().map(|(profile, state)| (hand, *profile, state)))
+		         .flat_map(move |(hand, profile, state)| {
+			         self.bindings
+			             .sources(profile, action)
+			             .iter()
+			             .filter(move |source| source.hand == hand)
+			             .map(move |source| (hand, source.kind, state))
+		         })
+	}
+}
+
+fn axis_value(kind: SourceKind, state: &ControllerInput) -> Vector2<f32> {
+	match kind {
+		SourceKind::Button(button) => Vector2::new(if state.buttons & (1u64 << button) != 0 { 1.0 } else { 0.0 }, 0.0),
+		SourceKind::Axis(axis) => Vector2::new(state.axes[axis][0], state.axes[axis][1]),
+		SourceKind::AxisX(axis) => Vector2::new(state.axes[axis][0], 0.0),
+		SourceKind::AxisY(axis) => Vector2::new(state.axes[axis][1], 0.0),
+	}
+}
+
+fn is_held(kind: SourceKind, state: &ControllerInput) -> bool {
+	match kind {
+		SourceKind::Button(button) => state.buttons & (1u64 << button) != 0,
+		_ => {
+			let value = axis_value(kind, state);
+			value.x.abs().max(value.y.abs()) > AXIS_PRESS
+		},
+	}
+}
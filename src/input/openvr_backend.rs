@@ -0,0 +1,84 @@
+use std::time::Instant;
+use openvr::{System, TrackedControllerRole, TrackedDeviceIndex};
+use openvr_sys as sys;
+
+use super::{InputBackend, Hand, Profile, ControllerInput};
+use super::haptics::Pulse;
+
+// Longest pulse accepted by the legacy haptics API, in microseconds
+const MAX_PULSE: f32 = 3999.0;
+
+/// Reads controller state through the legacy OpenVR input API.
+pub struct OpenVRBackend {
+	system: System,
+	/// End time and strength of the pulse played on each hand.
+	pulses: [Option<(Instant, f32)>; 2],
+}
+
+impl OpenVRBackend {
+	pub fn new(system: System) -> OpenVRBackend {
+		OpenVRBackend {
+			system,
+			pulses: [None, None],
+		}
+	}
+	
+	fn device(&self, hand: Hand) -> Option<TrackedDeviceIndex> {
+		self.system.tracked_device_index_for_controller_role(match hand {
+			Hand::Left => TrackedControllerRole::LeftHand,
+			Hand::Right => TrackedControllerRole::RightHand,
+		})
+	}
+}
+
+impl InputBackend for OpenVRBackend {
+	fn profile(&self, hand: Hand) -> Option<Profile> {
+		let device = self.device(hand)?;
+		let controller_type = self.system
+		                          .string_tracked_device_property(device, sys::ETrackedDeviceProperty_Prop_ControllerType_String)
+		                          .map(|name| name.to_string_lossy().into_owned())
+		                          .unwrap_or_default();
+		
+		Some(match controller_type.as_str() {
+			"vive_controller" => Profile::Vive,
+			"knuckles" => Profile::Index,
+			"oculus_touch" => Profile::Touch,
+			_ => Profile::Generic,
+		})
+	}
+	
+	fn state(&self, hand: Hand) -> Option<ControllerInput> {
+		let state = self.system.controller_state(self.device(hand)?)?;
+		let mut axes = [[0.0; 2]; 5];
+		
+		for (axis, value) in axes.iter_mut().zip(state.axis.iter()) {
+			*axis = [value.x, value.y];
+		}
+		
+		Some(ControllerInput {
+			buttons: state.button_pressed,
+			axes,
+		})
+	}
+	
+	fn haptic_pulse(&mut self, hand: Hand, pulse: Pulse) {
+		self.pulses[hand.index()] = Some((Instant::now() + pulse.duration, pulse.strength));
+	}
+	
+	/// Legacy haptics play a single short pulse, so longer ones are repeated every frame.
+	/// Strength sets the length of these short pulses.
+	fn update(&mut self) {
+		let now = Instant::now();
+		
+		for &hand in &Hand::ALL {
+			match self.pulses[hand.index()] {
+				Some((end, strength)) if end > now => {
+					if let Some(device) = self.device(hand) {
+						self.system.trigger_haptic_pulse(device, 0, (strength * MAX_PULSE) as u16);
+					}
+				},
+				_ => self.pulses[hand.index()] = None,
+			}
+		}
+	}
+}
@@ -0,0 +1,287 @@
+use cgmath::{Matrix4, Vector3, Point3, Rad, Deg, InnerSpace, SquareMatrix, EuclideanSpace, Transform, Zero};
+
+use crate::scene::{Scene, NodeId};
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture, Vertex};
+use crate::renderer::lod::LodModel;
+use crate::input::{Input, Action};
+use crate::config::Config;
+
+/// Stick deflection ignored by smooth movement.
+const DEAD_ZONE: f32 = 0.15;
+/// Stick deflection which triggers a snap turn, and below which the stick is considered released again.
+const SNAP_THRESHOLD: (f32, f32) = (0.7, 0.3);
+/// Turning speed in degrees per second when snap turning is disabled.
+const SMOOTH_TURN_SPEED: f32 = 90.0;
+/// Stick deflection forward which starts aiming a teleport.
+const AIM_THRESHOLD: f32 = 0.5;
+/// Launch speed in m/s, time step in seconds and number of steps of the simulated teleport arc.
+const ARC_SPEED: f32 = 7.0;
+const ARC_STEP: f32 = 0.05;
+const ARC_SEGMENTS: usize = 40;
+const ARC_WIDTH: f32 = 0.01;
+/// Steepest slope in degrees which can be teleported onto.
+const MAX_SLOPE: f32 = 30.0;
+/// Half size of the teleport target marker in meters.
+const MARKER_SIZE: f32 = 0.2;
+const VALID_COLOR: [u8; 4] = [64, 200, 255, 255];
+const INVALID_COLOR: [u8; 4] = [255, 64, 64, 255];
+
+/// Moves the play space through the scene. While active, sticks move, turn and aim teleports instead of rotating the eyes:
+/// left stick moves relative to the head, right stick turns left and right and aims a teleport arc from the right hand
+/// while pushed forward, teleporting onto a walkable surface when released.
+pub struct Locomotion {
+	pub active: bool,
+	move_speed: f32,
+	/// Radians, zero turns smoothly.
+	snap_angle: f32,
+	/// Turn stick hasn't returned to the center since the last snap turn.
+	turned: bool,
+	/// Target of the teleport being aimed, if it's valid.
+	target: Option<Point3<f32>>,
+	aiming: bool,
+	arc: NodeId,
+	segments: Vec<NodeId>,
+	marker: NodeId,
+	/// Segment and marker models for valid and invalid targets.
+	segment_models: (LodModel, LodModel),
+	marker_models: (LodModel, LodModel),
+}
+
+impl Locomotion {
+	/// Adds hidden nodes of the teleport arc to the scene.
+	pub fn new(scene: &mut Scene, renderer: &Renderer, config: &Config) -> Result<Locomotion, ModelError> {
+		let textures = (Texture::from_color(VALID_COLOR, renderer)?, Texture::from_color(INVALID_COLOR, renderer)?);
+		let segment = |texture: &Texture| box_model(Point3::new(-0.5, -0.5, -1.0), Point3::new(0.5, 0.5, 0.0), texture, renderer);
+		let marker = |texture: &Texture| box_model(Point3::new(-MARKER_SIZE, 0.0, -MARKER_SIZE), Point3::new(MARKER_SIZE, 0.01, MARKER_SIZE), texture, renderer);
+		let segment_models = (segment(&textures.0)?, segment(&textures.1)?);
+		let marker_models = (marker(&textures.0)?, marker(&textures.1)?);
+		
+		let arc = scene.add(scene.root(), "teleport_arc", None, Matrix4::identity());
+		scene.get_mut(arc).unwrap().visible = false;
+		
+		let segments = (0..ARC_SEGMENTS).map(|index| scene.add(arc, format!("segment{}", index), Some(segment_models.0.clone()), Matrix4::identity()))
+		                                .collect();
+		let marker = scene.add(arc, "target", Some(marker_models.0.clone()), Matrix4::identity());
+		
+		Ok(Locomotion {
+			active: config.locomotion,
+			move_speed: config.move_speed,
+			snap_angle: Rad::from(Deg(config.snap_turn)).0,
+			turned: false,
+			target: None,
+			aiming: false,
+			arc,
+			segments,
+			marker,
+			segment_models,
+			marker_models,
+		})
+	}
+	
+	/// Moves the play space node. `hmd` is the HMD pose in tracking space, `hand` the node aiming teleports.
+	pub fn update(&mut self, scene: &mut Scene, input: &Input, play_space: NodeId, hand: NodeId, hmd: Matrix4<f32>, dt: f32) {
+		let aiming = self.active && input.axis(Action::Teleport).y > AIM_THRESHOLD && scene.is_visible(hand);
+		
+		if self.aiming && !aiming {
+			if let Some(target) = self.target.take() {
+				// Teleport puts the point on the floor under the HMD onto the target
+				let feet = scene.world_transform(play_space).transform_point(Point3::new(hmd.w.x, 0.0, hmd.w.z));
+				move_node(scene, play_space, Matrix4::from_translation(target - feet));
+			}
+		}
+		
+		self.aiming = aiming;
+		scene.get_mut(self.arc).unwrap().visible = aiming;
+		
+		if !self.active {
+			return;
+		}
+		
+		if aiming {
+			self.aim(scene, play_space, hand);
+		}
+		
+		let hmd = scene.world_transform(play_space) * hmd;
+		
+		let stick = input.axis(Action::Move);
+		if stick.magnitude() > DEAD_ZONE {
+			let stick = stick.normalize_to((stick.magnitude().min(1.0) - DEAD_ZONE) / (1.0 - DEAD_ZONE));
+			let forward = horizontal(-hmd.z.truncate());
+			let right = horizontal(hmd.x.truncate());
+			
+			move_node(scene, play_space, Matrix4::from_translation((right * stick.x + forward * stick.y) * self.move_speed * dt));
+		}
+		
+		let turn = input.axis(Action::Turn).x;
+		let angle = if self.snap_angle == 0.0 {
+			if turn.abs() > DEAD_ZONE { -turn * Rad::from(Deg(SMOOTH_TURN_SPEED)).0 * dt } else { 0.0 }
+		} else if turn.abs() > SNAP_THRESHOLD.0 && !self.turned {
+			self.turned = true;
+			-turn.signum() * self.snap_angle
+		} else {
+			if turn.abs() < SNAP_THRESHOLD.1 {
+				self.turned = false;
+			}
+			0.0
+		};
+		
+		if angle != 0.0 {
+			// Turns around the head, so the view doesn't swing around the play space's origin
+			let head = Vector3::new(hmd.w.x, 0.0, hmd.w.z);
+			move_node(scene, play_space, Matrix4::from_translation(head) * Matrix4::from_angle_y(Rad(angle)) * Matrix4::from_translation(-head));
+		}
+	}
+	
+	/// Traces the arc from the hand and shows it, colored by whether it ends on a walkable surface.
+	fn aim(&mut self, scene: &mut Scene, play_space: NodeId, hand: NodeId) {
+		let pose = scene.world_transform(hand);
+		let direction = -pose.z.truncate().normalize();
+		let mut points = vec![Point3::from_vec(pose.w.truncate())];
+		let mut hit = None;
+		
+		for step in 1..=ARC_SEGMENTS {
+			let time = step as f32 * ARC_STEP;
+			let point = points[0] + direction * ARC_SPEED * time + Vector3::new(0.0, -9.81, 0.0) * 0.5 * time * time;
+			let previous = points[points.len() - 1];
+			
+			hit = raycast(scene, scene.root(), Matrix4::identity(), &[play_space, self.arc], previous, point - previous);
+			
+			match hit {
+				Some((position, _)) => {
+					points.push(position);
+					break;
+				},
+				None => points.push(point),
+			}
+		}
+		
+		self.target = hit.filter(|(_, normal)| normal.y >= Rad::from(Deg(MAX_SLOPE)).0.cos())
+		                 .map(|(position, _)| position);
+		
+		let (segment_model, marker_model) = match self.target {
+			Some(_) => (&self.segment_models.0, &self.marker_models.0),
+			None => (&self.segment_models.1, &self.marker_models.1),
+		};
+		
+		for (index, &segment) in self.segments.iter().enumerate() {
+			let node = scene.get_mut(segment).unwrap();
+			node.visible = index + 1 < points.len();
+			node.model = Some(segment_model.clone());
+			
+			if node.visible {
+				node.transform = segment_transform(points[index], points[index + 1]);
+			}
+		}
+		
+		let marker = scene.get_mut(self.marker).unwrap();
+		marker.visible = hit.is_some();
+		marker.model = Some(marker_model.clone());
+		marker.transform = Matrix4::from_translation(points[points.len() - 1].to_vec());
+	}
+}
+
+/// Applies a world space transform to a node under the root.
+fn move_node(scene: &mut Scene, node: NodeId, transform: Matrix4<f32>) {
+	let node = scene.get_mut(node).unwrap();
+	node.transform = transform * node.transform;
+}
+
+/// Direction projected to the floor, zero if it's vertical.
+fn horizontal(direction: Vector3<f32>) -> Vector3<f32> {
+	let direction = Vector3::new(direction.x, 0.0, direction.z);
+	if direction.magnitude2() > std::f32::EPSILON { direction.normalize() } else { Vector3::zero() }
+}
+
+/// Stretches the unit segment model, pointing along -Z, between two points.
+fn segment_transform(from: Point3<f32>, to: Point3<f32>) -> Matrix4<f32> {
+	let direction = to - from;
+	let side = direction.cross(Vector3::unit_y());
+	let side = if side.magnitude2() > std::f32::EPSILON { side.normalize() } else { Vector3::unit_x() };
+	let up = side.cross(direction).normalize_to(ARC_WIDTH);
+	
+	Matrix4::from_cols((side * ARC_WIDTH).extend(0.0), up.extend(0.0), (-direction).extend(0.0), from.to_homogeneous())
+}
+
+/// Closest intersection of a segment from `origin` to `origin + direction` with triangles of visible models in the subtree,
+/// skipping excluded nodes. Returns the world position and normal facing the segment's origin.
+fn raycast(scene: &Scene, id: NodeId, parent: Matrix4<f32>, exclude: &[NodeId], origin: Point3<f32>, direction: Vector3<f32>) -> Option<(Point3<f32>, Vector3<f32>)> {
+	let node = match scene.get(id) {
+		Some(node) if node.visible && !exclude.contains(&id) => node,
+		_ => return None,
+	};
+	
+	let world = parent * node.transform;
+	let mut closest: Option<(f32, Point3<f32>, Vector3<f32>)> = None;
+	
+	if let (Some(model), Some(inverse)) = (&node.model, world.invert()) {
+		let model = &model.levels()[0].model;
+		let local_origin = inverse.transform_point(origin);
+		let local_direction = inverse.transform_vector(direction);
+		
+		if model.bounds.intersect_ray(local_origin, local_direction).map_or(false, |distance| distance <= 1.0) {
+			for triangle in model.triangles.iter() {
+				if let Some(distance) = intersect_triangle(triangle, local_origin, local_direction) {
+					if distance <= 1.0 && closest.map_or(true, |(nearest, _, _)| distance < nearest) {
+						let [a, b, c] = [world.transform_point(triangle[0]), world.transform_point(triangle[1]), world.transform_point(triangle[2])];
+						let normal = (b - a).cross(c - a);
+						let normal = if normal.dot(direction) > 0.0 { -normal } else { normal };
+						
+						closest = Some((distance, origin + direction * distance, normal.normalize()));
+					}
+				}
+			}
+		}
+	}
+	
+	for &child in node.children() {
+		if let Some((position, normal)) = raycast(scene, child, world, exclude, origin, direction) {
+			let distance = (position - origin).dot(direction) / direction.magnitude2();
+			if closest.map_or(true, |(nearest, _, _)| distance < nearest) {
+				closest = Some((distance, position, normal));
+			}
+		}
+	}
+	
+	closest.map(|(_, position, normal)| (position, normal))
+}
+
+/// Möller–Trumbore intersection, in units of the direction's length. Triangles are double-sided.
+fn intersect_triangle(triangle: &[Point3<f32>; 3], origin: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+	let edges = (triangle[1] - triangle[0], triangle[2] - triangle[0]);
+	let p = direction.cross(edges.1);
+	let determinant = edges.0.dot(p);
+	
+	if determinant.abs() < std::f32::EPSILON {
+		return None;
+	}
+	
+	let offset = origin - triangle[0];
+	let u = offset.dot(p) / determinant;
+	let q = offset.cross(edges.0);
+	let v = direction.dot(q) / determinant;
+	
+	if u < 0.0 || v < 0.0 || u + v > 1.0 {
+		return None;
+	}
+	
+	Some(edges.1.dot(q) / determinant).filter(|&distance| distance >= 0.0)
+}
+
+fn box_model(min: Point3<f32>, max: Point3<f32>, texture: &Texture, renderer: &Renderer) -> Result<LodModel, ModelError> {
+	let vertices: Vec<Vertex> = (0..8).map(|corner| Vertex::new(if corner & 1 == 0 { min.x } else { max.x },
+	                                                          if corner & 2 == 0 { min.y } else { max.y },
+	                                                          if corner & 4 == 0 { min.z } else { max.z },
+	                                                          0.5, 0.5))
+	                                  .collect();
+	let indices = [
+		0, 2, 1, 1, 2, 3,
+		4, 5, 6, 5, 7, 6,
+		0, 1, 4, 1, 5, 4,
+		2, 6, 3, 3, 6, 7,
+		0, 4, 2, 2, 4, 6,
+		1, 3, 5, 3, 7, 5,
+	];
+	
+	Ok(LodModel::new(Model::with_texture(&vertices, &indices, texture.clone(), renderer)?))
+}
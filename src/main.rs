@@ -6,17 +6,25 @@ mod shaders;
 mod renderer;
 mod application;
 mod openvr_vulkan;
+mod config;
+mod eye_rotation;
+mod input;
+mod devices;
+mod scene;
+mod behaviour;
+mod animation;
+mod script;
+mod grab;
+mod locomotion;
+mod chaperone;
 
 use application::Application;
+use config::Config;
 
 fn main() -> Result<(), Box<dyn Error>> {
 	let args: Vec<String> = env::args().collect();
 	let program = args[0].clone();
-	let mut opts = Options::new();
-	
-	opts.optopt("d", "device", "Select fallback device to use", "NUMBER");
-	opts.optflag("", "debug", "Enable debugging layer and info");
-	opts.optflag("h", "help", "Print this help menu");
+	let opts = Config::options();
 	
 	let matches = opts.parse(&args[1..])?;
 	
@@ -25,10 +33,9 @@ fn main() -> Result<(), Box<dyn Error>> {
 		return Ok(());
 	}
 	
-	let device = matches.opt_get("d")?;
-	let debug = matches.opt_present("debug");
+	let config = Config::from_matches(&matches)?;
 	
-	let application = Application::new(device, debug)?;
+	let application = Application::new(config)?;
 	
 	application.run()?;
 	
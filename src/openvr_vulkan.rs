@@ -1,9 +1,14 @@
 use std::ffi::CString;
+use std::os::raw::c_void;
+use std::{mem, ptr};
+use err_derive::Error;
 use vulkano::instance::{Instance, PhysicalDevice};
 use vulkano::{VulkanObject, SynchronizedVulkanObject};
 use vulkano::device::{Device, Queue};
 use vulkano::image::{AttachmentImage, ImageAccess};
 use openvr::{VkInstance_T, VkPhysicalDevice_T, Compositor, VkDevice_T, VkQueue_T};
+use openvr::compositor::texture::{vulkan, ColorSpace, Bounds};
+use openvr_sys as sys;
 use cgmath::{Matrix4, Matrix};
 
 pub fn vulkan_device_extensions_required(compositor: &Compositor, physical: &PhysicalDevice) -> Vec<CString> {
@@ -60,3 +65,130 @@ impl<F: 'static + Send + Sync> OpenVRPtr for AttachmentImage<F> {
 		self.inner().image.internal_object() as Self::PtrType
 	}
 }
+
+/// Depth buffer submitted along with eye's color texture, used by the compositor for reprojection.
+pub struct DepthTexture {
+	pub texture: vulkan::Texture,
+	/// Projection used to render the depth buffer, including near and far planes.
+	pub projection: Matrix4<f32>,
+	/// Range of depth values stored in the texture.
+	pub range: (f32, f32),
+}
+
+// Texture_t, VRTextureWithPose_t and VRTextureWithDepth_t merged, bindgen doesn't handle inheritance
+#[repr(C)]
+struct TextureWithPoseAndDepth {
+	handle: *mut c_void,
+	texture_type: sys::ETextureType,
+	color_space: sys::EColorSpace,
+	device_to_absolute_tracking: sys::HmdMatrix34_t,
+	depth: sys::VRTextureDepthInfo_t,
+}
+
+/// Raw compositor interface, for functionality not exposed by `openvr::Compositor`.
+pub struct RawCompositor(&'static sys::VR_IVRCompositor_FnTable);
+
+impl RawCompositor {
+	/// # Safety
+	///
+	/// OpenVR has to be initialized and the result must not be used after the `openvr::Context` is shut down.
+	pub unsafe fn load() -> Option<RawCompositor> {
+		load_fn_table(sys::IVRCompositor_Version).map(RawCompositor)
+	}
+	
+	/// Submits eye's color texture together with its depth buffer and pose used to render it.
+	/// If `bounds` is None, the entire texture will be used.
+	///
+	/// # Safety
+	///
+	/// The handles you supply must be valid and comply with the Vulkan's synchronization requirements.
+	pub unsafe fn submit_with_depth(&self, eye: openvr::Eye, texture: &vulkan::Texture, color_space: ColorSpace, depth: &DepthTexture, bounds: Option<&Bounds>, pose: &[[f32; 4]; 3])
+	                               -> Result<(), SubmitError> {
+		let texture = TextureWithPoseAndDepth {
+			handle: texture as *const _ as *mut c_void,
+			texture_type: sys::ETextureType_TextureType_Vulkan,
+			color_space: color_space as sys::EColorSpace,
+			device_to_absolute_tracking: sys::HmdMatrix34_t { m: *pose },
+			depth: sys::VRTextureDepthInfo_t {
+				handle: &depth.texture as *const _ as *mut c_void,
+				mProjection: sys::HmdMatrix44_t { m: depth.projection.transpose().into() },
+				vRange: sys::HmdVector2_t { v: [depth.range.0, depth.range.1] },
+			},
+		};
+		
+		let error = self.0.Submit.unwrap()(eye as sys::EVREye,
+		                                   &texture as *const _ as *mut sys::Texture_t,
+		                                   bounds.map(|bounds| bounds as *const Bounds as *mut sys::VRTextureBounds_t)
+		                                         .unwrap_or(ptr::null_mut()),
+		                                   sys::EVRSubmitFlags_Submit_TextureWithPose | sys::EVRSubmitFlags_Submit_TextureWithDepth);
+		
+		if error == sys::EVRCompositorError_VRCompositorError_None {
+			Ok(())
+		} else {
+			Err(SubmitError(error))
+		}
+	}
+	
+	/// GPU time spent by the application on a frame `frames_ago` frames in the past, in milliseconds.
+	pub fn frame_gpu_time(&self, frames_ago: u32) -> Option<f32> {
+		unsafe {
+			let mut timing: sys::Compositor_FrameTiming = mem::zeroed();
+			timing.m_nSize = mem::size_of::<sys::Compositor_FrameTiming>() as u32;
+			
+			if self.0.GetFrameTiming.unwrap()(&mut timing, frames_ago) {
+				Some(timing.m_flPreSubmitGpuMs + timing.m_flPostSubmitGpuMs)
+			} else {
+				None
+			}
+		}
+	}
+}
+
+/// Raw chaperone setup interface, for the collision bounds not exposed by `openvr::Chaperone`.
+pub struct RawChaperoneSetup(&'static sys::VR_IVRChaperoneSetup_FnTable);
+
+impl RawChaperoneSetup {
+	/// # Safety
+	///
+	/// OpenVR has to be initialized and the result must not be used after the `openvr::Context` is shut down.
+	pub unsafe fn load() -> Option<RawChaperoneSetup> {
+		load_fn_table(sys::IVRChaperoneSetup_Version).map(RawChaperoneSetup)
+	}
+	
+	/// Corners of the wall quads set up by the user, in the standing tracking space.
+	pub fn live_collision_bounds(&self) -> Vec<[[f32; 3]; 4]> {
+		unsafe {
+			let mut count = 0;
+			self.0.GetLiveCollisionBoundsInfo.unwrap()(ptr::null_mut(), &mut count);
+			
+			let mut quads: Vec<sys::HmdQuad_t> = (0..count).map(|_| mem::zeroed()).collect();
+			if count == 0 || !self.0.GetLiveCollisionBoundsInfo.unwrap()(quads.as_mut_ptr(), &mut count) {
+				return Vec::new();
+			}
+			
+			quads.truncate(count as usize);
+			quads.iter()
+			     .map(|quad| [quad.vCorners[0].v, quad.vCorners[1].v, quad.vCorners[2].v, quad.vCorners[3].v])
+			     .collect()
+		}
+	}
+}
+
+/// Function table of an OpenVR interface with given version.
+unsafe fn load_fn_table<T>(version: &[u8]) -> Option<&'static T> {
+	let mut magic = Vec::from(b"FnTable:".as_ref());
+	magic.extend(version.iter());
+	
+	let mut error = sys::EVRInitError_VRInitError_None;
+	let table = sys::VR_GetGenericInterface(magic.as_ptr() as *const i8, &mut error) as *const T;
+	
+	if error != sys::EVRInitError_VRInitError_None || table.is_null() {
+		None
+	} else {
+		Some(&*table)
+	}
+}
+
+#[derive(Debug, Error)]
+#[error(display = "Compositor submit failed with error code {}", _0)]
+pub struct SubmitError(sys::EVRCompositorError);
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use err_derive::Error;
+use vulkano::device::Queue;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use crate::shaders;
+use crate::config::Config;
+use crate::eye_rotation::{EyeRotation, angle_between, radians};
+
+// Divergence, relative to the maximum, at which the vignette starts to appear
+const VIGNETTE_START: f32 = 0.25;
+
+pub type VignettePipeline = GraphicsPipeline<
+	BufferlessDefinition,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>
+>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VignetteParams {
+	pub strength: f32,
+}
+
+/// Darkens the periphery as the eyes diverge. Rotation itself is limited by the eye rotation controller.
+pub struct Comfort {
+	pub vignette: Option<Arc<VignettePipeline>>,
+	max_divergence: f32,
+}
+
+impl Comfort {
+	pub fn new(config: &Config, queue: &Queue, render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>) -> Result<Comfort, ComfortError> {
+		let device = queue.device();
+		
+		let vignette = if config.vignette {
+			let vs = shaders::fullscreen_vert::Shader::load(device.clone()).unwrap();
+			let fs = shaders::vignette_frag::Shader::load(device.clone()).unwrap();
+			
+			Some(Arc::new(
+				GraphicsPipeline::start()
+				                 .vertex_input(BufferlessDefinition)
+				                 .vertex_shader(vs.main_entry_point(), ())
+				                 .viewports_scissors_dynamic(1)
+				                 .fragment_shader(fs.main_entry_point(), ())
+				                 .blend_alpha_blending()
+				                 .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+				                 .build(device.clone())?
+			))
+		} else {
+			None
+		};
+		
+		Ok(Comfort {
+			vignette,
+			max_divergence: radians(config.max_divergence),
+		})
+	}
+	
+	/// Vignette parameters for given rotation, strengthening as the eyes approach maximum divergence.
+	pub fn params(&self, rotation: EyeRotation) -> VignetteParams {
+		let divergence = angle_between(rotation.0, rotation.1) / self.max_divergence.max(std::f32::EPSILON);
+		let strength = ((divergence - VIGNETTE_START) / (1.0 - VIGNETTE_START)).max(0.0).min(1.0);
+		
+		VignetteParams { strength }
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ComfortError {
+	#[error(display = "{}", _0)] GraphicsPipelineCreationError(#[error(source)] GraphicsPipelineCreationError),
+}
@@ -7,26 +7,29 @@ use vulkano::format;
 use vulkano::device::Queue;
 use openvr::compositor::texture::{vulkan, Handle, ColorSpace};
 use openvr::compositor::Texture;
-use cgmath::Matrix4;
+use cgmath::{Matrix4, Point3, Transform, EuclideanSpace};
 
-use crate::openvr_vulkan::OpenVRPtr;
+use crate::openvr_vulkan::{OpenVRPtr, DepthTexture};
+use super::CLIP;
 
 pub struct Eye {
 	pub image: Arc<AttachmentImage<format::R8G8B8A8Srgb>>,
-	pub depth_image: Arc<AttachmentImage<format::D16Unorm>>,
+	pub depth_image: Arc<AttachmentImage<Format>>,
 	pub texture: Texture,
+	pub depth_texture: DepthTexture,
+	/// Projection used for drawing, in Vulkan's clip space.
 	pub projection: Matrix4<f32>,
+	pub head_to_eye: Matrix4<f32>,
 	pub frame_buffer: Arc<dyn FramebufferAbstract + Send + Sync>,
 }
 
 pub const IMAGE_FORMAT: Format = Format::R8G8B8A8Srgb;
-pub const DEPTH_FORMAT: Format = Format::D16Unorm;
 
 impl Eye {
-	pub fn new<RPD>(recommended_size:(u32, u32), projection: Matrix4<f32>, queue: &Queue, render_pass: &Arc<RPD>)
-	               -> Result<Eye, EyeCreationError>
-	               where RPD: RenderPassAbstract + Sync + Send + 'static {
-		let dimensions = [recommended_size.0, recommended_size.1];
+	/// Creates render targets for an eye. `projection` is in OpenVR's convention, which the compositor expects for depth.
+	pub fn new(size: (u32, u32), projection: Matrix4<f32>, eye_to_head: Matrix4<f32>, depth_format: Format, queue: &Queue, render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>)
+	          -> Result<Eye, EyeCreationError> {
+		let dimensions = [size.0, size.1];
 		
 		let device = queue.device();
 		
@@ -38,7 +41,14 @@ impl Eye {
 		                                                     sampled: true,
 		                                                     ..ImageUsage::none() })?;
 		
-		let depth_image = AttachmentImage::transient(device.clone(), dimensions, format::D16Unorm)?;
+		// Depth is stored and shared with the compositor, so it can be used for reprojection
+		let depth_image = AttachmentImage::with_usage(device.clone(),
+		                                              dimensions,
+		                                              depth_format,
+		                                              ImageUsage { transfer_source: true,
+		                                                           sampled: true,
+		                                                           depth_stencil_attachment: true,
+		                                                           ..ImageUsage::none() })?;
 		
 		let texture = Texture {
 			handle: Handle::Vulkan(vulkan::Texture {
@@ -56,6 +66,22 @@ impl Eye {
 			color_space: ColorSpace::Gamma,
 		};
 		
+		let depth_texture = DepthTexture {
+			texture: vulkan::Texture {
+				image: (*depth_image).as_ptr(),
+				device: device.as_ptr(),
+				physical_device: device.physical_device().as_ptr(),
+				instance: device.instance().as_ptr(),
+				queue: queue.as_ptr(),
+				queue_family_index: queue.family().id(),
+				width: depth_image.dimensions().width(),
+				height: depth_image.dimensions().height(),
+				format: depth_image.format() as u32,
+				sample_count: depth_image.samples(),
+			},
+			projection,
+			range: (0.0, 1.0),
+		};
 		
 		let frame_buffer = Arc::new(Framebuffer::start(render_pass.clone())
 		                       .add(image.clone())?
@@ -66,10 +92,33 @@ impl Eye {
 			image,
 			depth_image,
 			texture,
-			projection,
+			depth_texture,
+			projection: CLIP * projection,
+			head_to_eye: eye_to_head.inverse_transform().unwrap(),
 			frame_buffer,
 		})
 	}
+	
+	/// Creates the same eye with render targets of different size.
+	pub fn resized(&self, size: (u32, u32), queue: &Queue, render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>)
+	              -> Result<Eye, EyeCreationError> {
+		Eye::new(size, self.depth_texture.projection, self.head_to_eye.inverse_transform().unwrap(), self.depth_image.format(), queue, render_pass)
+	}
+	
+	pub fn size(&self) -> (u32, u32) {
+		let dimensions = self.image.dimensions();
+		(dimensions.width(), dimensions.height())
+	}
+	
+	/// Position of the eye, including its offset, for a head at given transform.
+	pub fn position(&self, head: Matrix4<f32>) -> Point3<f32> {
+		head.transform_point(self.head_to_eye.inverse_transform().unwrap().transform_point(Point3::origin()))
+	}
+	
+	/// Vertical scale of the projection, used to estimate on-screen size of objects.
+	pub fn focal(&self) -> f32 {
+		self.projection.y.y.abs()
+	}
 }
 
 #[derive(Debug, Error)]
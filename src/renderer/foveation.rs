@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use err_derive::Error;
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, FramebufferCreationError, RenderPassAbstract, Subpass};
+use vulkano::image::{AttachmentImage, ImageUsage, ImageCreationError};
+use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode, SamplerCreationError};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet, PersistentDescriptorSetError, PersistentDescriptorSetBuildError};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::depth_stencil::{DepthStencil, Compare};
+use cgmath::{Matrix4, Vector2, Vector4};
+
+use crate::shaders;
+use crate::renderer::eye::IMAGE_FORMAT;
+
+// Resolution of the periphery relative to the eye's render target
+pub const PERIPHERY_SCALE: f32 = 0.5;
+
+pub type UpscalePipeline = GraphicsPipeline<
+	BufferlessDefinition,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>
+>;
+
+/// Low resolution render target covering the whole field of view of an eye.
+pub struct PeripheryTarget {
+	pub frame_buffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+	pub set: Arc<dyn DescriptorSet + Send + Sync>,
+	pub size: (u32, u32),
+}
+
+#[repr(C)]
+pub struct UpscaleParams {
+	fovea: [f32; 4],
+	uv_scale: [f32; 2],
+}
+
+/// Fixed foveated rendering using multi-resolution split. The whole view is rendered at reduced resolution first,
+/// then the center (fovea) is rendered at full resolution and the rest is filled by upscaling the periphery.
+pub struct Foveation {
+	pub pipeline: Arc<UpscalePipeline>,
+	pub targets: (PeripheryTarget, PeripheryTarget),
+	/// Half-size of the fovea, relative to half of the view.
+	radius: f32,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	color_sampler: Arc<Sampler>,
+	depth_sampler: Arc<Sampler>,
+}
+
+impl Foveation {
+	pub fn new(radius: f32, eye_size: (u32, u32), depth_format: Format, queue: &Queue, render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>)
+	          -> Result<Foveation, FoveationError> {
+		let device = queue.device();
+		
+		let vs = shaders::fullscreen_vert::Shader::load(device.clone()).unwrap();
+		let fs = shaders::upscale_frag::Shader::load(device.clone()).unwrap();
+		
+		let pipeline = Arc::new(
+			GraphicsPipeline::start()
+			                 .vertex_input(BufferlessDefinition)
+			                 .vertex_shader(vs.main_entry_point(), ())
+			                 .viewports_scissors_dynamic(1)
+			                 .fragment_shader(fs.main_entry_point(), ())
+			                 .depth_stencil(DepthStencil { depth_compare: Compare::Always,
+			                                               ..DepthStencil::simple_depth_test() })
+			                 .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+			                 .build(device.clone())?
+		);
+		
+		let color_sampler = Sampler::new(device.clone(), Filter::Linear, Filter::Linear, MipmapMode::Nearest,
+		                                 SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge,
+		                                 0.0, 1.0, 0.0, 0.0)?;
+		let depth_sampler = Sampler::new(device.clone(), Filter::Nearest, Filter::Nearest, MipmapMode::Nearest,
+		                                 SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge,
+		                                 0.0, 1.0, 0.0, 0.0)?;
+		
+		let targets = (
+			create_target(eye_size, depth_format, queue, render_pass, &pipeline, &color_sampler, &depth_sampler)?,
+			create_target(eye_size, depth_format, queue, render_pass, &pipeline, &color_sampler, &depth_sampler)?,
+		);
+		
+		Ok(Foveation {
+			pipeline,
+			targets,
+			radius: radius.max(0.0).min(1.0),
+			render_pass: render_pass.clone(),
+			color_sampler,
+			depth_sampler,
+		})
+	}
+	
+	/// Recreates periphery targets to match new size of the eye's render targets.
+	pub fn resize(&mut self, eye_size: (u32, u32), depth_format: Format, queue: &Queue) -> Result<(), FoveationError> {
+		self.targets = (
+			create_target(eye_size, depth_format, queue, &self.render_pass, &self.pipeline, &self.color_sampler, &self.depth_sampler)?,
+			create_target(eye_size, depth_format, queue, &self.render_pass, &self.pipeline, &self.color_sampler, &self.depth_sampler)?,
+		);
+		
+		Ok(())
+	}
+	
+	/// Fovea rectangle (min.xy, max.xy) in render area's texture coordinates, centered where `gaze` projects.
+	/// `gaze` is the eye's projection and rotation, so the fovea follows the rotated eye.
+	pub fn fovea(&self, gaze: Matrix4<f32>) -> [f32; 4] {
+		let clip = gaze * Vector4::new(0.0, 0.0, -1.0, 0.0);
+		let center = if clip.w > 0.0 {
+			Vector2::new(clip.x / clip.w, clip.y / clip.w) * 0.5 + Vector2::new(0.5, 0.5)
+		} else {
+			Vector2::new(0.5, 0.5)
+		};
+		
+		[
+			center.x - self.radius * 0.5,
+			center.y - self.radius * 0.5,
+			center.x + self.radius * 0.5,
+			center.y + self.radius * 0.5,
+		]
+	}
+	
+	pub fn params(&self, fovea: [f32; 4], render_size: (u32, u32), target: &PeripheryTarget) -> UpscaleParams {
+		let periphery_size = periphery_size(render_size);
+		
+		UpscaleParams {
+			fovea,
+			uv_scale: [periphery_size.0 as f32 / target.size.0 as f32, periphery_size.1 as f32 / target.size.1 as f32],
+		}
+	}
+}
+
+/// Part of the periphery target rendered to, for given size of the eye's render area.
+pub fn periphery_size(render_size: (u32, u32)) -> (u32, u32) {
+	(((render_size.0 as f32 * PERIPHERY_SCALE).round() as u32).max(1),
+	 ((render_size.1 as f32 * PERIPHERY_SCALE).round() as u32).max(1))
+}
+
+fn create_target(eye_size: (u32, u32),
+                 depth_format: Format,
+                 queue: &Queue,
+                 render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+                 pipeline: &Arc<UpscalePipeline>,
+                 color_sampler: &Arc<Sampler>,
+                 depth_sampler: &Arc<Sampler>)
+                 -> Result<PeripheryTarget, FoveationError> {
+	let device = queue.device();
+	let size = periphery_size(eye_size);
+	let dimensions = [size.0, size.1];
+	
+	let image = AttachmentImage::with_usage(device.clone(),
+	                                        dimensions,
+	                                        IMAGE_FORMAT,
+	                                        ImageUsage { sampled: true,
+	                                                     color_attachment: true,
+	                                                     ..ImageUsage::none() })?;
+	
+	let depth_image = AttachmentImage::with_usage(device.clone(),
+	                                              dimensions,
+	                                              depth_format,
+	                                              ImageUsage { sampled: true,
+	                                                           depth_stencil_attachment: true,
+	                                                           ..ImageUsage::none() })?;
+	
+	let frame_buffer = Arc::new(Framebuffer::start(render_pass.clone())
+	                       .add(image.clone())?
+	                       .add(depth_image.clone())?
+	                       .build()?);
+	
+	let set = Arc::new(
+		PersistentDescriptorSet::start(pipeline.descriptor_set_layout(0).ok_or(FoveationError::NoLayout)?.clone())
+		                        .add_sampled_image(image.clone(), color_sampler.clone())?
+		                        .add_sampled_image(depth_image.clone(), depth_sampler.clone())?
+		                        .build()?
+	);
+	
+	Ok(PeripheryTarget {
+		frame_buffer,
+		set,
+		size,
+	})
+}
+
+#[derive(Debug, Error)]
+pub enum FoveationError {
+	#[error(display = "Pipeline doesn't have layout set 0")] NoLayout,
+	#[error(display = "{}", _0)] GraphicsPipelineCreationError(#[error(source)] GraphicsPipelineCreationError),
+	#[error(display = "{}", _0)] SamplerCreationError(#[error(source)] SamplerCreationError),
+	#[error(display = "{}", _0)] ImageCreationError(#[error(source)] ImageCreationError),
+	#[error(display = "{}", _0)] FramebufferCreationError(#[error(source)] FramebufferCreationError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetError(#[error(source)] PersistentDescriptorSetError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetBuildError(#[error(source)] PersistentDescriptorSetBuildError),
+}
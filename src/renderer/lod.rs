@@ -0,0 +1,107 @@
+use std::str::FromStr;
+use cgmath::{Matrix4, Point3, Transform, MetricSpace, InnerSpace};
+
+use crate::renderer::model::Model;
+
+/// Condition under which a level of detail is used.
+#[derive(Debug, Copy, Clone)]
+pub enum LodThreshold {
+	/// Level is used while the object is closer to the eye than given distance.
+	Distance(f32),
+	/// Level is used while the object's bounding sphere covers at least given fraction of the eye's view height.
+	ScreenSize(f32),
+}
+
+impl LodThreshold {
+	fn accepts(&self, distance: f32, screen_size: f32) -> bool {
+		match *self {
+			LodThreshold::Distance(max) => distance < max,
+			LodThreshold::ScreenSize(min) => screen_size >= min,
+		}
+	}
+}
+
+impl FromStr for LodThreshold {
+	type Err = String;
+	
+	/// Parses a distance in meters, or a screen size in percent when followed by `%`.
+	fn from_str(s: &str) -> Result<LodThreshold, String> {
+		let invalid = || format!("Invalid LOD threshold: {}, expected DISTANCE or SIZE%", s);
+		
+		if s.ends_with('%') {
+			Ok(LodThreshold::ScreenSize(s.trim_end_matches('%').parse::<f32>().map_err(|_| invalid())? / 100.0))
+		} else {
+			Ok(LodThreshold::Distance(s.parse().map_err(|_| invalid())?))
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct LodLevel {
+	pub model: Model,
+	pub threshold: LodThreshold,
+}
+
+/// Chain of meshes representing a single scene object, ordered from the most to the least detailed one.
+/// Object is culled if none of the levels accept current distance and screen size.
+#[derive(Clone)]
+pub struct LodModel {
+	levels: Vec<LodLevel>,
+	hidden_inside: bool,
+}
+
+impl LodModel {
+	pub fn new(model: Model) -> LodModel {
+		LodModel {
+			levels: vec![LodLevel { model, threshold: LodThreshold::Distance(std::f32::INFINITY) }],
+			hidden_inside: false,
+		}
+	}
+	
+	pub fn from_levels(levels: Vec<LodLevel>) -> LodModel {
+		assert!(!levels.is_empty(), "LodModel requires at least one level");
+		
+		LodModel {
+			levels,
+			hidden_inside: false,
+		}
+	}
+	
+	/// Culls the object for eyes inside its bounding sphere, like the model of the headset they belong to.
+	pub fn hidden_inside(mut self) -> LodModel {
+		self.hidden_inside = true;
+		self
+	}
+	
+	pub fn levels(&self) -> &[LodLevel] {
+		&self.levels
+	}
+	
+	pub fn loaded(&self) -> bool {
+		self.levels.iter().any(|level| level.model.loaded())
+	}
+	
+	/// Picks the level to draw for an eye at `eye` with vertical projection scale `focal`.
+	/// If the wanted level is still loading, closest loaded level is used instead.
+	pub fn select(&self, eye: Point3<f32>, focal: f32, matrix: &Matrix4<f32>) -> Option<&Model> {
+		let bounds = &self.levels[0].model.bounds;
+		let center = matrix.transform_point(bounds.center());
+		let scale = matrix.x.truncate().magnitude()
+		                  .max(matrix.y.truncate().magnitude())
+		                  .max(matrix.z.truncate().magnitude());
+		
+		let distance = eye.distance(center);
+		if self.hidden_inside && distance < bounds.radius() * scale {
+			return None;
+		}
+		
+		let screen_size = bounds.radius() * scale * focal / distance.max(std::f32::EPSILON);
+		
+		let wanted = self.levels.iter().position(|level| level.threshold.accepts(distance, screen_size))?;
+		
+		self.levels[wanted..].iter()
+		                     .chain(self.levels[..wanted].iter().rev())
+		                     .map(|level| &level.model)
+		                     .find(|model| model.loaded())
+	}
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use err_derive::Error;
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, FramebufferCreationError, RenderPassAbstract, RenderPassCreationError};
+use vulkano::image::{ImageUsage, SwapchainImage};
+use vulkano::instance::Instance;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, BeginRenderPassError, AutoCommandBufferBuilderContextError, BuildError, CommandBufferExecError};
+use vulkano::swapchain::{self, Swapchain, Surface, SurfaceTransform, PresentMode, FullscreenExclusive, ColorSpace, AcquireError, SwapchainCreationError, CapabilitiesError};
+use vulkano::sync::{self, GpuFuture, FlushError};
+use vulkano::OomError;
+use vulkano_win::VkSurfaceBuild;
+use winit::event_loop::{EventLoop, ControlFlow};
+use winit::event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode};
+use winit::window::{Window, WindowBuilder};
+use winit::platform::desktop::EventLoopExtDesktop;
+use winit::dpi::LogicalSize;
+
+use crate::renderer::eye::Eye;
+use crate::renderer::stereo::{StereoCompositor, StereoMode, StereoError, fit_viewport};
+
+/// Desktop window showing the eye images for spectators. It's drawn after the frame is submitted to the compositor
+/// and skipped whenever the window has no image ready, so it never holds back the headset.
+pub struct Mirror {
+	pub mode: StereoMode,
+	/// When the current mode was selected, for timing wiggle stereo.
+	mode_start: Instant,
+	/// Window was closed and the mirror should be dropped.
+	pub closed: bool,
+	event_loop: EventLoop<()>,
+	surface: Arc<Surface<Window>>,
+	swapchain: Arc<Swapchain<Window>>,
+	frame_buffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	stereo: StereoCompositor,
+	recreate_swapchain: bool,
+}
+
+impl Mirror {
+	pub fn new(mode: StereoMode, instance: &Arc<Instance>, queue: &Arc<Queue>) -> Result<Mirror, MirrorError> {
+		let device = queue.device();
+		let event_loop = EventLoop::new();
+		let surface = WindowBuilder::new().with_title("vkeyes mirror")
+		                                  .with_inner_size(LogicalSize::new(1280.0, 720.0))
+		                                  .build_vk_surface(&event_loop, instance.clone())?;
+		
+		if !surface.is_supported(queue.family())? {
+			return Err(MirrorError::Unsupported);
+		}
+		
+		let capabilities = surface.capabilities(device.physical_device())?;
+		let format = [Format::B8G8R8A8Srgb, Format::R8G8B8A8Srgb].iter()
+		                                                         .find(|&&format| capabilities.supported_formats.contains(&(format, ColorSpace::SrgbNonLinear)))
+		                                                         .cloned()
+		                                                         .unwrap_or(capabilities.supported_formats[0].0);
+		
+		// Presenting without waiting for the desktop's vertical blank keeps the queue free for the next VR frame
+		let present_mode = if capabilities.present_modes.mailbox {
+			PresentMode::Mailbox
+		} else if capabilities.present_modes.immediate {
+			PresentMode::Immediate
+		} else {
+			PresentMode::Fifo
+		};
+		
+		let (swapchain, images) = Swapchain::new(device.clone(),
+		                                         surface.clone(),
+		                                         capabilities.min_image_count,
+		                                         format,
+		                                         window_size(&surface),
+		                                         1,
+		                                         ImageUsage::color_attachment(),
+		                                         queue,
+		                                         SurfaceTransform::Identity,
+		                                         capabilities.supported_composite_alpha.iter().next().unwrap(),
+		                                         present_mode,
+		                                         FullscreenExclusive::Default,
+		                                         true,
+		                                         ColorSpace::SrgbNonLinear)?;
+		
+		let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+			vulkano::single_pass_renderpass!(device.clone(),
+				attachments: {
+					color: {
+						load: Clear,
+						store: Store,
+						format: swapchain.format(),
+						samples: 1,
+					}
+				},
+				pass: {
+					color: [color],
+					depth_stencil: {}
+				}
+			)?
+		);
+		
+		let stereo = StereoCompositor::new(device, &render_pass)?;
+		
+		Ok(Mirror {
+			mode,
+			mode_start: Instant::now(),
+			closed: false,
+			event_loop,
+			surface,
+			frame_buffers: create_frame_buffers(&images, &render_pass)?,
+			swapchain,
+			render_pass,
+			stereo,
+			recreate_swapchain: false,
+		})
+	}
+	
+	/// Draws the eye images into the window after the frame in `previous` has been submitted.
+	/// `render_size` is the part of the eye images rendered to.
+	pub fn present(&mut self, previous: Box<dyn GpuFuture>, queue: &Arc<Queue>, eyes: &(Eye, Eye), render_size: (u32, u32))
+	              -> Result<Box<dyn GpuFuture>, MirrorError> {
+		self.handle_events();
+		
+		if self.closed {
+			return Ok(previous);
+		}
+		
+		if self.recreate_swapchain {
+			let size = window_size(&self.surface);
+			if size[0] == 0 || size[1] == 0 {
+				return Ok(previous);
+			}
+			
+			let (swapchain, images) = match self.swapchain.recreate_with_dimensions(size) {
+				Ok(result) => result,
+				Err(SwapchainCreationError::UnsupportedDimensions) => return Ok(previous),
+				Err(err) => return Err(err.into()),
+			};
+			
+			self.frame_buffers = create_frame_buffers(&images, &self.render_pass)?;
+			self.swapchain = swapchain;
+			self.recreate_swapchain = false;
+		}
+		
+		let (index, suboptimal, acquire) = match swapchain::acquire_next_image(self.swapchain.clone(), Some(Duration::from_secs(0))) {
+			Ok(result) => result,
+			Err(AcquireError::Timeout) => return Ok(previous),
+			Err(AcquireError::OutOfDate) => {
+				self.recreate_swapchain = true;
+				return Ok(previous);
+			},
+			Err(err) => return Err(err.into()),
+		};
+		
+		self.recreate_swapchain |= suboptimal;
+		
+		let mode = self.mode.frame_at(self.mode_start.elapsed());
+		let dynamic_state = fit_viewport(self.swapchain.dimensions(), (render_size.0 * mode.width(), render_size.1));
+		
+		let command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(queue.device().clone(), queue.family())?
+		                                              .begin_render_pass(self.frame_buffers[index].clone(), false, vec![[0.0, 0.0, 0.0, 1.0].into()])?;
+		let command_buffer = self.stereo.draw(command_buffer, &dynamic_state, eyes, render_size, mode)?
+		                                .end_render_pass()?
+		                                .build()?;
+		
+		let future = previous.join(acquire)
+		                     .then_execute(queue.clone(), command_buffer)?
+		                     .then_swapchain_present(queue.clone(), self.swapchain.clone(), index)
+		                     .then_signal_fence_and_flush();
+		
+		match future {
+			Ok(future) => Ok(Box::new(future)),
+			Err(FlushError::OutOfDate) => {
+				self.recreate_swapchain = true;
+				Ok(Box::new(sync::now(queue.device().clone())))
+			},
+			Err(err) => Err(err.into()),
+		}
+	}
+	
+	/// Processes pending window events without blocking. Tab cycles through the stereo modes.
+	fn handle_events(&mut self) {
+		let mode = &mut self.mode;
+		let mode_start = &mut self.mode_start;
+		let closed = &mut self.closed;
+		let recreate_swapchain = &mut self.recreate_swapchain;
+		
+		self.event_loop.run_return(|event, _, control_flow| {
+			match event {
+				Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => *closed = true,
+				Event::WindowEvent { event: WindowEvent::Resized(_), .. } => *recreate_swapchain = true,
+				Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed,
+				                                                                                 virtual_keycode: Some(VirtualKeyCode::Tab),
+				                                                                                 .. }, .. }, .. } => {
+					*mode = mode.next();
+					*mode_start = Instant::now();
+					println!("Mirror stereo mode: {}", mode);
+				},
+				Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+				_ => {},
+			}
+		});
+	}
+}
+
+fn window_size(surface: &Surface<Window>) -> [u32; 2] {
+	let size = surface.window().inner_size();
+	[size.width, size.height]
+}
+
+fn create_frame_buffers(images: &[Arc<SwapchainImage<Window>>], render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>)
+                       -> Result<Vec<Arc<dyn FramebufferAbstract + Send + Sync>>, FramebufferCreationError> {
+	images.iter()
+	      .map(|image| -> Result<Arc<dyn FramebufferAbstract + Send + Sync>, _> {
+		      Ok(Arc::new(Framebuffer::start(render_pass.clone())
+		                              .add(image.clone())?
+		                              .build()?))
+	      })
+	      .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum MirrorError {
+	#[error(display = "Mirror window can't be presented from the render queue.")] Unsupported,
+	#[error(display = "{}", _0)] SurfaceCreationError(#[error(source)] vulkano_win::CreationError),
+	#[error(display = "{}", _0)] CapabilitiesError(#[error(source)] CapabilitiesError),
+	#[error(display = "{}", _0)] SwapchainCreationError(#[error(source)] SwapchainCreationError),
+	#[error(display = "{}", _0)] AcquireError(#[error(source)] AcquireError),
+	#[error(display = "{}", _0)] RenderPassCreationError(#[error(source)] RenderPassCreationError),
+	#[error(display = "{}", _0)] StereoError(#[error(source)] StereoError),
+	#[error(display = "{}", _0)] FramebufferCreationError(#[error(source)] FramebufferCreationError),
+	#[error(display = "{}", _0)] OomError(#[error(source)] OomError),
+	#[error(display = "{}", _0)] BeginRenderPassError(#[error(source)] BeginRenderPassError),
+	#[error(display = "{}", _0)] AutoCommandBufferBuilderContextError(#[error(source)] AutoCommandBufferBuilderContextError),
+	#[error(display = "{}", _0)] BuildError(#[error(source)] BuildError),
+	#[error(display = "{}", _0)] CommandBufferExecError(#[error(source)] CommandBufferExecError),
+	#[error(display = "{}", _0)] FlushError(#[error(source)] FlushError),
+}
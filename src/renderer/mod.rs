@@ -1,4 +1,6 @@
 use std::sync::Arc;
+use std::path::Path;
+use std::thread;
 use err_derive::Error;
 use vulkano::{app_info_from_cargo_toml, OomError};
 use vulkano::device::{Device, DeviceExtensions, RawDeviceExtensions, Features, Queue, DeviceCreationError};
@@ -7,22 +9,49 @@ use vulkano::instance::{Instance, InstanceExtensions, RawInstanceExtensions, Phy
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineCreationError};
 use vulkano::sync::{GpuFuture, FlushError};
 use vulkano::sync;
-use vulkano::pipeline::viewport::Viewport;
-use vulkano::framebuffer::{Subpass, RenderPassCreationError, RenderPassAbstract};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, BeginRenderPassError, AutoCommandBufferBuilderContextError, BuildError, CommandBufferExecError, DrawIndexedError};
-use vulkano::format::ClearValue;
-use openvr::{System, Compositor};
-use cgmath::{Matrix4, Transform, Matrix, Vector2, Euler, Rad};
-use openvr::compositor::CompositorError;
+use vulkano::pipeline::viewport::{Viewport, Scissor};
+use vulkano::pipeline::vertex::BufferlessVertices;
+use vulkano::framebuffer::{Framebuffer, FramebufferCreationError, Subpass, RenderPassCreationError, RenderPassAbstract};
+use vulkano::image::{AttachmentImage, ImageUsage, ImageCreationError};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, BeginRenderPassError, AutoCommandBufferBuilderContextError, BuildError, CommandBufferExecError, DrawIndexedError, DrawError, CopyBufferImageError};
+use vulkano::buffer::{CpuAccessibleBuffer, CpuBufferPool, BufferUsage};
+use vulkano::buffer::cpu_access::ReadLockError;
+use vulkano::memory::DeviceMemoryAllocError;
+use vulkano::format::{ClearValue, Format};
+use vulkano::pipeline::depth_stencil::{DepthStencil, Compare};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet, UnsafeDescriptorSetLayout, PersistentDescriptorSetError, PersistentDescriptorSetBuildError};
+use openvr::{System, Compositor, tracked_device_index, property};
+use openvr::compositor::texture::Bounds;
+use cgmath::{Matrix4, Transform, Vector3, Point3};
+use openvr::compositor::texture::Handle;
+use image::{ImageBuffer, Rgba, ImageError};
 
 pub mod model;
+pub mod lod;
+pub mod skin;
 mod eye;
+mod projection;
+mod scaling;
+mod foveation;
+mod comfort;
+pub mod mirror;
+pub mod stereo;
 
 use crate::shaders;
 use crate::openvr_vulkan::*;
 use crate::renderer::eye::EyeCreationError;
-use crate::renderer::model::Model;
+use crate::renderer::scaling::ResolutionScaler;
+use crate::renderer::foveation::{Foveation, FoveationError, periphery_size};
+use crate::renderer::comfort::{Comfort, ComfortError};
+use crate::renderer::mirror::{Mirror, MirrorError};
+use crate::renderer::stereo::{StereoCompositor, StereoMode, StereoError, fit_viewport, save_animation};
+use crate::renderer::skin::{SkinUniform, ModelConstants};
+use crate::config::Config;
+use crate::eye_rotation::EyeRotation;
+use crate::scene::{Scene, Draw};
 use eye::Eye;
+use projection::projection_matrix;
 
 // workaround https://github.com/vulkano-rs/vulkano/issues/709
 type PipelineType = GraphicsPipeline<
@@ -38,22 +67,44 @@ pub struct Renderer {
 	queue: Arc<Queue>,
 	load_queue: Arc<Queue>,
 	pipeline: Arc<PipelineType>,
+	skin_layout: Arc<UnsafeDescriptorSetLayout>,
+	skin_pool: CpuBufferPool<SkinUniform>,
+	/// Identity joint palette, used by models which aren't deformed.
+	rigid_skin: Arc<dyn DescriptorSet + Send + Sync>,
+	/// Empty morph target buffer, used by models without morph targets.
+	no_morphs: Arc<dyn DescriptorSet + Send + Sync>,
+	render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
 	eyes: (Eye, Eye),
-	compositor: Compositor,
+	recommended_size: (u32, u32),
+	scaler: ResolutionScaler,
+	foveation: Option<Foveation>,
+	comfort: Option<Comfort>,
+	compositor: RawCompositor,
+	mirror: Option<Mirror>,
+	/// Combines the eyes into exported images.
+	stereo: StereoCompositor,
+	stereo_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+	clear_depth: f32,
 	previous_frame_end: Option<Box<dyn GpuFuture>>,
 }
 
-// Translates OpenGL projection matrix to Vulkan
+// Translates OpenVR projection matrix to Vulkan. Depth is already in 0..1 range, only Y axis has to be flipped.
 const CLIP: Matrix4<f32> = Matrix4::new(
 	1.0, 0.0, 0.0, 0.0,
 	0.0,-1.0, 0.0, 0.0,
-	0.0, 0.0, 0.5, 0.0,
-	0.0, 0.0, 0.5, 1.0,
+	0.0, 0.0, 1.0, 0.0,
+	0.0, 0.0, 0.0, 1.0,
 );
 
 impl Renderer {
-	pub fn new(system: &System, compositor: Compositor, device: Option<usize>, debug: bool) -> Result<Renderer, RendererCreationError> {
+	pub fn new(system: &System, compositor: Compositor, config: &Config) -> Result<Renderer, RendererCreationError> {
 		let recommended_size = system.recommended_render_target_size();
+		let debug = config.debug;
+		let depth_format = if config.reversed_z { Format::D32Sfloat } else { Format::D16Unorm };
+		let display_frequency = system.float_tracked_device_property(tracked_device_index::HMD, property::DisplayFrequency_Float)
+		                              .unwrap_or(90.0);
+		let scaler = ResolutionScaler::new(config.render_scale, config.adaptive_resolution, display_frequency);
+		let eye_size = scaled_size(recommended_size, scaler.scale().max(1.0));
 		
 		if debug {
 			println!("List of Vulkan debugging layers available to use:");
@@ -65,9 +116,11 @@ impl Renderer {
 		
 		let instance = {
 			let app_infos = app_info_from_cargo_toml!();
+			let window_extensions = if config.mirror.is_some() { vulkano_win::required_extensions() } else { InstanceExtensions::none() };
 			let extensions = RawInstanceExtensions::new(compositor.vulkan_instance_extensions_required())
 			                                       .union(&(&InstanceExtensions { ext_debug_utils: debug,
-			                                                                      ..InstanceExtensions::none() }).into());
+			                                                                      ..InstanceExtensions::none() }).into())
+			                                       .union(&(&window_extensions).into());
 			
 			let layers = if debug {
 				             vec!["VK_LAYER_LUNARG_standard_validation"]
@@ -132,7 +185,7 @@ impl Renderer {
 		                     .and_then(|ptr| PhysicalDevice::enumerate(&instance).find(|physical| physical.as_ptr() == ptr))
 		                     .or_else(|| {
 			                     println!("Failed to fetch device from openvr, using fallback");
-			                     PhysicalDevice::enumerate(&instance).skip(device.unwrap_or(0)).next()
+			                     PhysicalDevice::enumerate(&instance).skip(config.device.unwrap_or(0)).next()
 		                     })
 		                     .ok_or(RendererCreationError::NoDevices)?;
 		
@@ -176,7 +229,7 @@ impl Renderer {
 		let vs = shaders::vert::Shader::load(device.clone()).unwrap();
 		let fs = shaders::frag::Shader::load(device.clone()).unwrap();
 		
-		let render_pass = Arc::new(
+		let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
 			vulkano::single_pass_renderpass!(device.clone(),
 				attachments: {
 					color: {
@@ -187,8 +240,8 @@ impl Renderer {
 					},
 					depth: {
 						load: Clear,
-						store: DontCare,
-						format: eye::DEPTH_FORMAT,
+						store: Store,
+						format: depth_format,
 						samples: 1,
 					}
 				},
@@ -203,29 +256,80 @@ impl Renderer {
 			GraphicsPipeline::start()
 			                 .vertex_input_single_buffer::<model::Vertex>()
 			                 .vertex_shader(vs.main_entry_point(), ())
-			                 .viewports(Some(Viewport { origin: [0.0, 0.0],
-			                                            dimensions: [recommended_size.0 as f32, recommended_size.1 as f32],
-			                                            depth_range: 0.0 .. 1.0 }))
+			                 .viewports_scissors_dynamic(1)
 			                 .fragment_shader(fs.main_entry_point(), ())
-			                 .depth_stencil_simple_depth()
-			                 .render_pass(Subpass::from(render_pass.clone() as Arc<dyn RenderPassAbstract + Send + Sync>, 0).unwrap())
+			                 .blend_alpha_blending()
+			                 .depth_stencil(DepthStencil { depth_compare: if config.reversed_z { Compare::Greater } else { Compare::Less },
+			                                               ..DepthStencil::simple_depth_test() })
+			                 .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
 			                 .build(device.clone())?
 		);
 		
+		let skin_layout = pipeline.descriptor_set_layout(1).ok_or(RendererCreationError::NoLayout)?.clone();
+		let skin_pool = CpuBufferPool::uniform_buffer(device.clone());
+		
+		let rigid_skin = Arc::new(
+			PersistentDescriptorSet::start(skin_layout.clone())
+			                        .add_buffer(CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::uniform_buffer(), false, SkinUniform::new(&[], &[]))?)?
+			                        .build()?
+		);
+		
+		let no_morphs = Arc::new(
+			PersistentDescriptorSet::start(pipeline.descriptor_set_layout(2).ok_or(RendererCreationError::NoLayout)?.clone())
+			                        .add_buffer(CpuAccessibleBuffer::from_iter(device.clone(),
+			                                                                   BufferUsage{ storage_buffer: true, ..BufferUsage::none() },
+			                                                                   false,
+			                                                                   [[0.0f32; 4]].iter().cloned())?)?
+			                        .build()?
+		);
+		
 		let eyes = {
-			let proj_left : Matrix4<f32> = CLIP
-			                             * Matrix4::from(system.projection_matrix(openvr::Eye::Left,  0.1, 1000.1)).transpose()
-			                             * mat4(&system.eye_to_head_transform(openvr::Eye::Left )).inverse_transform().unwrap();
-			let proj_right: Matrix4<f32> = CLIP
-			                             * Matrix4::from(system.projection_matrix(openvr::Eye::Right, 0.1, 1000.1)).transpose()
-			                             * mat4(&system.eye_to_head_transform(openvr::Eye::Right)).inverse_transform().unwrap();
+			let proj_left : Matrix4<f32> = projection_matrix(&system.projection_raw(openvr::Eye::Left ), config.near, config.far, config.reversed_z);
+			let proj_right: Matrix4<f32> = projection_matrix(&system.projection_raw(openvr::Eye::Right), config.near, config.far, config.reversed_z);
+			
+			let eye_left  = eye_to_head(mat4(&system.eye_to_head_transform(openvr::Eye::Left )), config.ipd_scale, config.eye_offsets.0);
+			let eye_right = eye_to_head(mat4(&system.eye_to_head_transform(openvr::Eye::Right)), config.ipd_scale, config.eye_offsets.1);
 			
 			(
-				Eye::new(recommended_size, proj_left,  &queue, &render_pass)?,
-				Eye::new(recommended_size, proj_right, &queue, &render_pass)?,
+				Eye::new(eye_size, proj_left,  eye_left,  depth_format, &queue, &render_pass)?,
+				Eye::new(eye_size, proj_right, eye_right, depth_format, &queue, &render_pass)?,
 			)
 		};
 		
+		let foveation = config.foveation
+		                      .map(|radius| Foveation::new(radius, eye_size, depth_format, &queue, &render_pass))
+		                      .transpose()?;
+		
+		let comfort = if config.comfort {
+			Some(Comfort::new(config, &queue, &render_pass)?)
+		} else {
+			None
+		};
+		
+		let compositor = unsafe { RawCompositor::load() }.ok_or(RendererCreationError::NoCompositor)?;
+		
+		let stereo_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+			vulkano::single_pass_renderpass!(device.clone(),
+				attachments: {
+					color: {
+						load: Clear,
+						store: Store,
+						format: eye::IMAGE_FORMAT,
+						samples: 1,
+					}
+				},
+				pass: {
+					color: [color],
+					depth_stencil: {}
+				}
+			)?
+		);
+		let stereo = StereoCompositor::new(&device, &stereo_pass)?;
+		
+		let mirror = config.mirror
+		                   .map(|mode| Mirror::new(mode, &instance, &queue))
+		                   .transpose()?;
+		
 		let previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<_>);
 		
 		Ok(Renderer {
@@ -234,69 +338,210 @@ impl Renderer {
 			queue,
 			load_queue,
 			pipeline,
+			skin_layout,
+			skin_pool,
+			rigid_skin,
+			no_morphs,
+			render_pass,
 			eyes,
+			recommended_size,
+			scaler,
+			foveation,
+			comfort,
 			compositor,
+			mirror,
+			stereo,
+			stereo_pass,
+			clear_depth: if config.reversed_z { 0.0 } else { 1.0 },
 			previous_frame_end,
 		})
 	}
 	
-	pub fn render(&mut self, hmd_pose: &[[f32; 4]; 3], eye_rotation: (Vector2<f32>, Vector2<f32>), scene: &mut [(Model, Matrix4<f32>)]) -> Result<(), RenderError> {
+	pub fn render_scale(&self) -> f32 {
+		self.scaler.scale()
+	}
+	
+	/// Sets render target size relative to the recommended one. Eye targets are recreated only when they are too small,
+	/// otherwise only a part of them is rendered to and submitted.
+	pub fn set_render_scale(&mut self, scale: f32) -> Result<f32, RenderError> {
+		let scale = self.scaler.set_scale(scale);
+		let size = scaled_size(self.recommended_size, scale);
+		let current = self.eyes.0.size();
+		
+		if size.0 > current.0 || size.1 > current.1 {
+			self.eyes = (
+				self.eyes.0.resized(size, &self.queue, &self.render_pass)?,
+				self.eyes.1.resized(size, &self.queue, &self.render_pass)?,
+			);
+			
+			if let Some(foveation) = &mut self.foveation {
+				foveation.resize(size, self.eyes.0.depth_image.format(), &self.queue)?;
+			}
+		}
+		
+		Ok(scale)
+	}
+	
+	pub fn adaptive_resolution(&self) -> bool {
+		self.scaler.adaptive()
+	}
+	
+	pub fn set_adaptive_resolution(&mut self, adaptive: bool) {
+		self.scaler.set_adaptive(adaptive);
+	}
+	
+	/// Saves what the eyes currently see, combined according to `mode`. Wiggle stereo is saved as a looping GIF,
+	/// other modes as a PNG. Images are encoded on a background thread, which logs the result.
+	pub fn screenshot(&mut self, path: &Path, mode: StereoMode) -> Result<(), RenderError> {
+		let render_size = scaled_size(self.recommended_size, self.scaler.scale());
+		let size = (render_size.0 * mode.width(), render_size.1);
+		
+		let image = AttachmentImage::with_usage(self.device.clone(),
+		                                        [size.0, size.1],
+		                                        eye::IMAGE_FORMAT,
+		                                        ImageUsage { color_attachment: true,
+		                                                     transfer_source: true,
+		                                                     ..ImageUsage::none() })?;
+		
+		let frame_buffer = Arc::new(Framebuffer::start(self.stereo_pass.clone())
+		                                       .add(image.clone())?
+		                                       .build()?);
+		
+		let mut frames = Vec::new();
+		
+		for frame in mode.frames() {
+			let buffer = CpuAccessibleBuffer::from_iter(self.device.clone(),
+			                                            BufferUsage::transfer_destination(),
+			                                            false,
+			                                            (0 .. size.0 * size.1 * 4).map(|_| 0u8))?;
+			
+			let command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family())?
+			                                              .begin_render_pass(frame_buffer.clone(), false, vec![[0.0, 0.0, 0.0, 1.0].into()])?;
+			let command_buffer = self.stereo.draw(command_buffer, &fit_viewport([size.0, size.1], size), &self.eyes, render_size, frame)?
+			                                .end_render_pass()?
+			                                .copy_image_to_buffer(image.clone(), buffer.clone())?
+			                                .build()?;
+			
+			// Replaced before submitting, so the next frame can still be rendered if the screenshot fails
+			let previous = self.previous_frame_end.replace(Box::new(sync::now(self.device.clone())) as Box<_>).unwrap();
+			
+			previous.then_execute(self.queue.clone(), command_buffer)?
+			        .then_signal_fence_and_flush()?
+			        .wait(None)?;
+			
+			frames.push(ImageBuffer::<Rgba<u8>, _>::from_raw(size.0, size.1, buffer.read()?.to_vec()).unwrap());
+		}
+		
+		let path = path.to_path_buf();
+		thread::spawn(move || {
+			let result = if mode == StereoMode::Wiggle {
+				save_animation(&path, frames).map_err(ImageError::from)
+			} else {
+				frames[0].save(&path)
+			};
+			
+			match result {
+				Ok(()) => println!("Saved {}", path.display()),
+				Err(err) => eprintln!("Failed to save {}: {}", path.display(), err),
+			}
+		});
+		
+		Ok(())
+	}
+	
+	pub fn render(&mut self, hmd_pose: &[[f32; 4]; 3], play_space: Matrix4<f32>, eye_rotation: EyeRotation, scene: &Scene) -> Result<(), RenderError> {
 		self.previous_frame_end.as_mut().unwrap().cleanup_finished();
 		
-		let left_pv = self.eyes.0.projection
-		            * Matrix4::from(Euler { x: Rad(eye_rotation.0.x),
-		                                    y: Rad(eye_rotation.0.y),
-		                                    z: Rad(0.0) })
-		            * mat4(hmd_pose).inverse_transform().unwrap();
-		
-		let right_pv = self.eyes.1.projection
-		             * Matrix4::from(Euler { x: Rad(eye_rotation.1.x),
-		                                     y: Rad(eye_rotation.1.y),
-		                                     z: Rad(0.0) })
-		             * mat4(hmd_pose).inverse_transform().unwrap();
-		
-		let mut command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())?
-		                                                  .begin_render_pass(self.eyes.0.frame_buffer.clone(),
-		                                                                     false,
-		                                                                     vec![ [0.5, 0.5, 0.5, 1.0].into(),
-		                                                                           ClearValue::Depth(1.0) ])?;
-		
-		for (model, matrix) in scene.iter_mut() {
-			if !model.loaded() { continue };
-			command_buffer = command_buffer.draw_indexed(self.pipeline.clone(),
-			                                             &DynamicState::none(),
-			                                             model.vertices.clone(),
-			                                             model.indices.clone(),
-			                                             model.set.clone(),
-			                                             left_pv * *matrix)?;
+		let scene = scene.draw_list();
+		let skins = scene.iter()
+		                 .map(|draw| self.skin(draw))
+		                 .collect::<Result<Vec<_>, _>>()?;
+		
+		if let Some(gpu_time) = self.compositor.frame_gpu_time(1) {
+			self.scaler.update(gpu_time);
 		}
 		
-		command_buffer = command_buffer.end_render_pass()?
-		                               .begin_render_pass(self.eyes.1.frame_buffer.clone(),
-		                                                  false,
-		                                                  vec![ [0.5, 0.5, 0.5, 1.0].into(),
-		                                                        ClearValue::Depth(1.0) ])?;
+		let vignette = match &self.comfort {
+			Some(comfort) => {
+				let params = comfort.params(eye_rotation);
+				comfort.vignette.clone().filter(|_| params.strength > 0.0).map(|pipeline| (pipeline, params))
+			},
+			None => None,
+		};
+		
+		let render_size = scaled_size(self.recommended_size, self.scaler.scale());
+		let eye_size = self.eyes.0.size();
+		let bounds = Bounds {
+			min: (0.0, 0.0),
+			max: (render_size.0 as f32 / eye_size.0 as f32, render_size.1 as f32 / eye_size.1 as f32),
+		};
 		
-		for (model, matrix) in scene.iter_mut() {
-			if !model.loaded() { continue };
-			command_buffer = command_buffer.draw_indexed(self.pipeline.clone(),
-			                                             &DynamicState::none(),
-			                                             model.vertices.clone(),
-			                                             model.indices.clone(),
-			                                             model.set.clone(),
-			                                             right_pv * *matrix)?;
+		let hmd = play_space * mat4(hmd_pose);
+		let view = hmd.inverse_transform().unwrap();
+		
+		let mut command_buffer = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())?;
+		
+		for &(eye, rotation, periphery) in &[(&self.eyes.0, eye_rotation.0, self.foveation.as_ref().map(|foveation| &foveation.targets.0)),
+		                                     (&self.eyes.1, eye_rotation.1, self.foveation.as_ref().map(|foveation| &foveation.targets.1))] {
+			let gaze = eye.projection
+			         * eye.head_to_eye
+			         * Matrix4::from(rotation);
+			let pv = gaze * view;
+			let eye_position = eye.position(hmd);
+			
+			let mut dynamic_state = viewport_state(render_size, [0.0, 0.0, 1.0, 1.0]);
+			let mut upscale = None;
+			
+			if let (Some(foveation), Some(periphery)) = (&self.foveation, periphery) {
+				command_buffer = command_buffer.begin_render_pass(periphery.frame_buffer.clone(),
+				                                                  false,
+				                                                  vec![ [0.5, 0.5, 0.5, 1.0].into(),
+				                                                        ClearValue::Depth(self.clear_depth) ])?;
+				command_buffer = self.draw_scene(command_buffer, &viewport_state(periphery_size(render_size), [0.0, 0.0, 1.0, 1.0]), pv, eye_position, eye.focal(), &scene, &skins)?
+				                     .end_render_pass()?;
+				
+				let fovea = foveation.fovea(gaze);
+				dynamic_state = viewport_state(render_size, fovea);
+				upscale = Some((foveation, periphery, foveation.params(fovea, render_size, periphery)));
+			}
+			
+			command_buffer = command_buffer.begin_render_pass(eye.frame_buffer.clone(),
+			                                                  false,
+			                                                  vec![ [0.5, 0.5, 0.5, 1.0].into(),
+			                                                        ClearValue::Depth(self.clear_depth) ])?;
+			command_buffer = self.draw_scene(command_buffer, &dynamic_state, pv, eye_position, eye.focal(), &scene, &skins)?;
+			
+			if let Some((foveation, periphery, params)) = upscale {
+				command_buffer = command_buffer.draw(foveation.pipeline.clone(),
+				                                     &viewport_state(render_size, [0.0, 0.0, 1.0, 1.0]),
+				                                     BufferlessVertices { vertices: 3, instances: 1 },
+				                                     periphery.set.clone(),
+				                                     params)?;
+			}
+			
+			if let Some((pipeline, params)) = &vignette {
+				command_buffer = command_buffer.draw(pipeline.clone(),
+				                                     &viewport_state(render_size, [0.0, 0.0, 1.0, 1.0]),
+				                                     BufferlessVertices { vertices: 3, instances: 1 },
+				                                     (),
+				                                     *params)?;
+			}
+			
+			command_buffer = command_buffer.end_render_pass()?;
 		}
 		
-		let command_buffer = command_buffer.end_render_pass()?
-		                                   .build()?;
+		let command_buffer = command_buffer.build()?;
 		
 		let future = self.previous_frame_end.take()
 		                                    .unwrap()
 		                                    .then_execute(self.queue.clone(), command_buffer)?;
 		
 		unsafe {
-			self.compositor.submit(openvr::Eye::Left,  &self.eyes.0.texture, None, Some(hmd_pose.clone()))?;
-			self.compositor.submit(openvr::Eye::Right, &self.eyes.1.texture, None, Some(hmd_pose.clone()))?;
+			for (side, eye) in &[(openvr::Eye::Left, &self.eyes.0), (openvr::Eye::Right, &self.eyes.1)] {
+				if let Handle::Vulkan(texture) = &eye.texture.handle {
+					self.compositor.submit_with_depth(*side, texture, eye.texture.color_space, &eye.depth_texture, Some(&bounds), hmd_pose)?;
+				}
+			}
 		}
 		
 		let future = future.then_signal_fence_and_flush();
@@ -312,15 +557,105 @@ impl Renderer {
 			Err(err) => return Err(err.into()),
 		}
 		
+		if let Some(mirror) = &mut self.mirror {
+			let previous = self.previous_frame_end.take().unwrap();
+			
+			// The headset keeps rendering without the mirror if it fails
+			match mirror.present(previous, &self.queue, &self.eyes, render_size) {
+				Ok(future) => {
+					self.previous_frame_end = Some(future);
+					
+					if mirror.closed {
+						self.mirror = None;
+					}
+				},
+				Err(err) => {
+					eprintln!("Mirror Error: {}, closing mirror", err);
+					self.mirror = None;
+					self.previous_frame_end = Some(Box::new(sync::now(self.device.clone())) as Box<_>);
+				},
+			}
+		}
+		
 		Ok(())
 	}
+	
+	fn draw_scene(&self,
+	              mut command_buffer: AutoCommandBufferBuilder,
+	              dynamic_state: &DynamicState,
+	              pv: Matrix4<f32>,
+	              eye_position: Point3<f32>,
+	              focal: f32,
+	              scene: &[Draw],
+	              skins: &[Arc<dyn DescriptorSet + Send + Sync>])
+	              -> Result<AutoCommandBufferBuilder, RenderError> {
+		for (draw, skin) in scene.iter().zip(skins) {
+			let model = match draw.model.select(eye_position, focal, &draw.transform) {
+				Some(model) => model,
+				None => continue,
+			};
+			command_buffer = command_buffer.draw_indexed(self.pipeline.clone(),
+			                                             dynamic_state,
+			                                             model.vertices.clone(),
+			                                             model.indices.clone(),
+			                                             (model.texture.set.clone(), skin.clone(), model.morphs.clone()),
+			                                             ModelConstants { mpv: (pv * draw.transform).into(),
+			                                                               morph_targets: model.morph_targets,
+			                                                               opacity: draw.opacity })?;
+		}
+		
+		Ok(command_buffer)
+	}
+	
+	/// Joint palette and morph weights of a drawn model, uploaded once per frame and shared by both eyes.
+	fn skin(&self, draw: &Draw) -> Result<Arc<dyn DescriptorSet + Send + Sync>, RenderError> {
+		if draw.joints.is_empty() && draw.morph_weights.is_empty() {
+			return Ok(self.rigid_skin.clone());
+		}
+		
+		let buffer = self.skin_pool.next(SkinUniform::new(&draw.joints, draw.morph_weights))?;
+		
+		Ok(Arc::new(
+			PersistentDescriptorSet::start(self.skin_layout.clone())
+			                        .add_buffer(buffer)?
+			                        .build()?
+		))
+	}
 }
 
 
+/// Viewport covering whole render area, with scissor limited to given rectangle (min.xy, max.xy) in texture coordinates.
+fn viewport_state(size: (u32, u32), scissor: [f32; 4]) -> DynamicState {
+	let min = [(scissor[0].max(0.0) * size.0 as f32) as u32, (scissor[1].max(0.0) * size.1 as f32) as u32];
+	let max = [(scissor[2].min(1.0) * size.0 as f32).ceil() as u32, (scissor[3].min(1.0) * size.1 as f32).ceil() as u32];
+	
+	DynamicState {
+		viewports: Some(vec![Viewport { origin: [0.0, 0.0],
+		                                dimensions: [size.0 as f32, size.1 as f32],
+		                                depth_range: 0.0 .. 1.0 }]),
+		scissors: Some(vec![Scissor { origin: [min[0] as i32, min[1] as i32],
+		                              dimensions: [max[0].saturating_sub(min[0]), max[1].saturating_sub(min[1])] }]),
+		..DynamicState::none()
+	}
+}
+
+/// Applies IPD scale and additional offset to OpenVR's eye to head transform.
+fn eye_to_head(mut transform: Matrix4<f32>, ipd_scale: f32, offset: [f32; 3]) -> Matrix4<f32> {
+	transform.w.x *= ipd_scale;
+	transform.w += Vector3::from(offset).extend(0.0);
+	transform
+}
+
+fn scaled_size(size: (u32, u32), scale: f32) -> (u32, u32) {
+	((size.0 as f32 * scale).round() as u32, (size.1 as f32 * scale).round() as u32)
+}
+
 #[derive(Debug, Error)]
 pub enum RendererCreationError {
 	#[error(display = "No devices available.")] NoDevices,
 	#[error(display = "No compute queue available.")] NoQueue,
+	#[error(display = "Failed to load OpenVR compositor interface.")] NoCompositor,
+	#[error(display = "Pipeline doesn't have the required layout set.")] NoLayout,
 	#[error(display = "{}", _0)] LayersListError(#[error(source)] LayersListError),
 	#[error(display = "{}", _0)] InstanceCreationError(#[error(source)] InstanceCreationError),
 	#[error(display = "{}", _0)] DeviceCreationError(#[error(source)] DeviceCreationError),
@@ -328,6 +663,13 @@ pub enum RendererCreationError {
 	#[error(display = "{}", _0)] RenderPassCreationError(#[error(source)] RenderPassCreationError),
 	#[error(display = "{}", _0)] GraphicsPipelineCreationError(#[error(source)] GraphicsPipelineCreationError),
 	#[error(display = "{}", _0)] EyeCreationError(#[error(source)] EyeCreationError),
+	#[error(display = "{}", _0)] FoveationError(#[error(source)] FoveationError),
+	#[error(display = "{}", _0)] ComfortError(#[error(source)] ComfortError),
+	#[error(display = "{}", _0)] MirrorError(#[error(source)] MirrorError),
+	#[error(display = "{}", _0)] StereoError(#[error(source)] StereoError),
+	#[error(display = "{}", _0)] DeviceMemoryAllocError(#[error(source)] DeviceMemoryAllocError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetError(#[error(source)] PersistentDescriptorSetError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetBuildError(#[error(source)] PersistentDescriptorSetBuildError),
 }
 
 #[derive(Debug, Error)]
@@ -335,9 +677,20 @@ pub enum RenderError {
 	#[error(display = "{}", _0)] OomError(#[error(source)] OomError),
 	#[error(display = "{}", _0)] BeginRenderPassError(#[error(source)] BeginRenderPassError),
 	#[error(display = "{}", _0)] DrawIndexedError(#[error(source)] DrawIndexedError),
+	#[error(display = "{}", _0)] DrawError(#[error(source)] DrawError),
 	#[error(display = "{}", _0)] AutoCommandBufferBuilderContextError(#[error(source)] AutoCommandBufferBuilderContextError),
 	#[error(display = "{}", _0)] BuildError(#[error(source)] BuildError),
 	#[error(display = "{}", _0)] CommandBufferExecError(#[error(source)] CommandBufferExecError),
-	#[error(display = "{}", _0)] CompositorError(#[error(source)] CompositorError),
+	#[error(display = "{}", _0)] SubmitError(#[error(source)] SubmitError),
+	#[error(display = "{}", _0)] EyeCreationError(#[error(source)] EyeCreationError),
+	#[error(display = "{}", _0)] FoveationError(#[error(source)] FoveationError),
 	#[error(display = "{}", _0)] FlushError(#[error(source)] FlushError),
+	#[error(display = "{}", _0)] StereoError(#[error(source)] StereoError),
+	#[error(display = "{}", _0)] ImageCreationError(#[error(source)] ImageCreationError),
+	#[error(display = "{}", _0)] FramebufferCreationError(#[error(source)] FramebufferCreationError),
+	#[error(display = "{}", _0)] DeviceMemoryAllocError(#[error(source)] DeviceMemoryAllocError),
+	#[error(display = "{}", _0)] CopyBufferImageError(#[error(source)] CopyBufferImageError),
+	#[error(display = "{}", _0)] ReadLockError(#[error(source)] ReadLockError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetError(#[error(source)] PersistentDescriptorSetError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetBuildError(#[error(source)] PersistentDescriptorSetBuildError),
 }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use std::time::Duration;
 use err_derive::Error;
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
 use vulkano::buffer::{ImmutableBuffer, BufferUsage};
 use vulkano::image::{ImmutableImage, Dimensions, ImageCreationError};
 use vulkano::sync::{GpuFuture, FlushError, FenceSignalFuture};
@@ -11,8 +11,10 @@ use vulkano::sampler::Sampler;
 use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet, PersistentDescriptorSetError, PersistentDescriptorSetBuildError};
 use vulkano::descriptor::PipelineLayoutAbstract;
 use arc_swap::ArcSwap;
+use cgmath::{Point3, Vector3, EuclideanSpace, MetricSpace};
 
 use crate::renderer::Renderer;
+use crate::renderer::skin::MAX_MORPH_TARGETS;
 use obj::TexturedVertex;
 use openvr::render_models;
 
@@ -24,16 +26,42 @@ pub const SCENE_PNG: &[u8] = include_bytes!("../../assets/scene.png");
 pub struct Model {
 	pub vertices: Arc<ImmutableBuffer<[Vertex]>>,
 	pub indices: Arc<ImmutableBuffer<[u16]>>,
-	pub image: Arc<ImmutableImage<Format>>,
-	pub set: Arc<dyn DescriptorSet + Send + Sync>,
-	fence: ArcSwap<FenceCheck>,
+	pub texture: Texture,
+	pub bounds: Bounds,
+	/// Triangles in the model's rest pose, kept for collision queries.
+	pub triangles: Arc<Vec<[Point3<f32>; 3]>>,
+	pub morph_targets: u32,
+	/// Position offsets of morph targets, shared empty buffer if there are none.
+	pub morphs: Arc<dyn DescriptorSet + Send + Sync>,
+	fence: Arc<ArcSwap<FenceCheck>>,
 }
 
 impl Model {
 	pub fn new(vertices: &[Vertex], indices: &[u16], source_image: DynamicImage, renderer: &Renderer) -> Result<Model, ModelError> {
-		let width = source_image.width();
-		let height = source_image.height();
+		Model::with_texture(vertices, indices, Texture::new(&source_image, renderer)?, renderer)
+	}
+	
+	/// Creates a model using an already uploaded texture, which can be shared with other models.
+	pub fn with_texture(vertices: &[Vertex], indices: &[u16], texture: Texture, renderer: &Renderer) -> Result<Model, ModelError> {
+		Model::with_morph_targets(vertices, indices, texture, &[], renderer)
+	}
+	
+	/// Creates a model which can be deformed by morph targets, each holding a position offset for every vertex.
+	pub fn with_morph_targets(vertices: &[Vertex], indices: &[u16], texture: Texture, targets: &[Vec<[f32; 3]>], renderer: &Renderer) -> Result<Model, ModelError> {
+		if targets.len() > MAX_MORPH_TARGETS {
+			return Err(ModelError::TooManyMorphTargets(targets.len()));
+		}
+		if targets.iter().any(|target| target.len() != vertices.len()) {
+			return Err(ModelError::InvalidMorphTarget);
+		}
+		
 		let queue = &renderer.load_queue;
+		let bounds = Bounds::from_vertices(vertices);
+		let triangles = indices.chunks_exact(3)
+		                       .filter_map(|triangle| Some([Point3::from(vertices.get(triangle[0] as usize)?.pos),
+		                                                    Point3::from(vertices.get(triangle[1] as usize)?.pos),
+		                                                    Point3::from(vertices.get(triangle[2] as usize)?.pos)]))
+		                       .collect();
 		
 		let (vertices, vertices_promise) = ImmutableBuffer::from_iter(vertices.iter().cloned(),
 		                                                              BufferUsage{ vertex_buffer: true, ..BufferUsage::none() },
@@ -43,6 +71,59 @@ impl Model {
 		                                                            BufferUsage{ index_buffer: true, ..BufferUsage::none() },
 		                                                            queue.clone())?;
 		
+		let (morphs, future): (_, Box<dyn GpuFuture>) = if targets.is_empty() {
+			(renderer.no_morphs.clone(), Box::new(vertices_promise.join(indices_promise)))
+		} else {
+			// Offsets are grouped by vertex, so the shader finds them at vertex index * target count
+			let offsets: Vec<[f32; 4]> = (0 .. targets[0].len()).flat_map(|vertex| targets.iter().map(move |target| [target[vertex][0], target[vertex][1], target[vertex][2], 0.0]))
+			                                                     .collect();
+			
+			let (offsets, offsets_promise) = ImmutableBuffer::from_iter(offsets.into_iter(),
+			                                                            BufferUsage{ storage_buffer: true, ..BufferUsage::none() },
+			                                                            queue.clone())?;
+			
+			let set = Arc::new(
+				PersistentDescriptorSet::start(renderer.pipeline.descriptor_set_layout(2).ok_or(ModelError::NoLayout)?.clone())
+				                        .add_buffer(offsets)?
+				                        .build()?
+			);
+			
+			(set as Arc<dyn DescriptorSet + Send + Sync>, Box::new(vertices_promise.join(indices_promise).join(offsets_promise)))
+		};
+		
+		let fence = Arc::new(ArcSwap::new(Arc::new(FenceCheck::new(future)?)));
+		
+		Ok(Model {
+			vertices,
+			indices,
+			texture,
+			bounds,
+			triangles: Arc::new(triangles),
+			morph_targets: targets.len() as u32,
+			morphs,
+			fence,
+		})
+	}
+	
+	pub fn loaded(&self) -> bool {
+		self.texture.loaded() && check_fence(&self.fence)
+	}
+}
+
+/// Uploaded image with its descriptor set, shareable between models.
+#[derive(Clone)]
+pub struct Texture {
+	pub image: Arc<ImmutableImage<Format>>,
+	pub set: Arc<dyn DescriptorSet + Send + Sync>,
+	fence: Arc<ArcSwap<FenceCheck>>,
+}
+
+impl Texture {
+	pub fn new(source_image: &DynamicImage, renderer: &Renderer) -> Result<Texture, ModelError> {
+		let width = source_image.width();
+		let height = source_image.height();
+		let queue = &renderer.load_queue;
+		
 		let (image, image_promise) = ImmutableImage::from_iter(source_image.to_rgba().into_vec().into_iter(),
 		                                                       Dimensions::Dim2d{ width, height },
 		                                                       Format::R8G8B8A8Unorm,
@@ -56,38 +137,107 @@ impl Model {
 			                        .build()?
 		);
 		
-		let fence = ArcSwap::new(Arc::new(FenceCheck::new(vertices_promise.join(indices_promise).join(image_promise))?));
+		let fence = Arc::new(ArcSwap::new(Arc::new(FenceCheck::new(image_promise)?)));
 		
-		Ok(Model {
-			vertices,
-			indices,
+		Ok(Texture {
 			image,
 			set,
 			fence,
 		})
 	}
 	
+	/// Single pixel texture of given color.
+	pub fn from_color(color: [u8; 4], renderer: &Renderer) -> Result<Texture, ModelError> {
+		Texture::new(&DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba(color))), renderer)
+	}
+	
 	pub fn loaded(&self) -> bool {
-		match &**self.fence.load() {
-			FenceCheck::Done(result) => *result,
-			FenceCheck::Pending(fence) => {
-				match fence.wait(Some(Duration::new(0, 0))) {
-					Err(FlushError::Timeout) => false,
-					Ok(()) => {
-						self.fence.swap(Arc::new(FenceCheck::Done(true)));
-						true
-					}
-					Err(err) => {
-						eprintln!("Error while loading model: {:?}", err);
-						self.fence.swap(Arc::new(FenceCheck::Done(false)));
-						false
-					}
+		check_fence(&self.fence)
+	}
+}
+
+/// Whether the upload guarded by the fence has finished. Fence is shared between clones, so it's only waited on until it signals once.
+fn check_fence(fence: &ArcSwap<FenceCheck>) -> bool {
+	match &**fence.load() {
+		FenceCheck::Done(result) => *result,
+		FenceCheck::Pending(pending) => {
+			match pending.wait(Some(Duration::new(0, 0))) {
+				Err(FlushError::Timeout) => false,
+				Ok(()) => {
+					fence.swap(Arc::new(FenceCheck::Done(true)));
+					true
+				}
+				Err(err) => {
+					eprintln!("Error while loading model: {:?}", err);
+					fence.swap(Arc::new(FenceCheck::Done(false)));
+					false
 				}
 			}
 		}
 	}
 }
 
+#[derive(Debug, Copy, Clone)]
+pub struct Bounds {
+	pub min: Point3<f32>,
+	pub max: Point3<f32>,
+}
+
+impl Bounds {
+	pub fn from_vertices(vertices: &[Vertex]) -> Bounds {
+		Bounds::from_points(vertices.iter().map(|vertex| Point3::from(vertex.pos)))
+	}
+	
+	/// Bounds of the origin if there are no points.
+	pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Bounds {
+		let mut iter = points.into_iter();
+		let first = iter.next().unwrap_or_else(Point3::origin);
+		
+		iter.fold(Bounds { min: first, max: first }, |bounds, pos| bounds.union(&Bounds { min: pos, max: pos }))
+	}
+	
+	pub fn union(&self, other: &Bounds) -> Bounds {
+		Bounds {
+			min: Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+			max: Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+		}
+	}
+	
+	pub fn center(&self) -> Point3<f32> {
+		self.min.midpoint(self.max)
+	}
+	
+	pub fn radius(&self) -> f32 {
+		self.min.distance(self.max) / 2.0
+	}
+	
+	/// Distance along the ray to the box, in units of the direction's length. Origin inside the box counts as a hit at 0.
+	pub fn intersect_ray(&self, origin: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+		let (mut near, mut far) = (0.0f32, std::f32::INFINITY);
+		
+		for axis in 0..3 {
+			let (min, max, origin, direction) = (self.min[axis], self.max[axis], origin[axis], direction[axis]);
+			
+			if direction.abs() < std::f32::EPSILON {
+				if origin < min || origin > max {
+					return None;
+				}
+				continue;
+			}
+			
+			let (first, second) = ((min - origin) / direction, (max - origin) / direction);
+			near = near.max(first.min(second));
+			far = far.min(first.max(second));
+			
+			if near > far {
+				return None;
+			}
+		}
+		
+		Some(near)
+	}
+}
+
 enum FenceCheck {
 	Done(bool),
 	Pending(FenceSignalFuture<Box<dyn GpuFuture>>)
@@ -104,7 +254,9 @@ impl FenceCheck {
 
 #[derive(Debug, Error)]
 pub enum ModelError {
-	#[error(display = "Pipeline doesn't have layout set 0")] NoLayout,
+	#[error(display = "Pipeline doesn't have the required layout set")] NoLayout,
+	#[error(display = "Model has {} morph targets, more than supported", _0)] TooManyMorphTargets(usize),
+	#[error(display = "Morph target doesn't have an offset for every vertex")] InvalidMorphTarget,
 	#[error(display = "{}", _0)] DeviceMemoryAllocError(#[error(source)] DeviceMemoryAllocError),
 	#[error(display = "{}", _0)] ImageCreationError(#[error(source)] ImageCreationError),
 	#[error(display = "{}", _0)] FlushError(#[error(source)] FlushError),
@@ -117,17 +269,37 @@ pub enum ModelError {
 pub struct Vertex {
 	pos: [f32; 3],
 	uv: [f32; 2],
+	joints: [u32; 4],
+	weights: [f32; 4],
 }
 
-vulkano::impl_vertex!(Vertex, pos, uv);
+vulkano::impl_vertex!(Vertex, pos, uv, joints, weights);
 
 impl Vertex {
+	/// Rigid vertex, following only the model's transform.
 	pub const fn new(x: f32, y: f32, z: f32, u: f32, v: f32) -> Self {
 		Vertex {
 			pos: [x, y, z],
 			uv: [u, v],
+			joints: [0; 4],
+			weights: [1.0, 0.0, 0.0, 0.0],
 		}
 	}
+	
+	/// Binds the vertex to up to four joints of the model's skin.
+	pub fn with_skin(mut self, joints: [u32; 4], weights: [f32; 4]) -> Self {
+		self.joints = joints;
+		self.weights = weights;
+		self
+	}
+	
+	pub fn pos(&self) -> [f32; 3] {
+		self.pos
+	}
+	
+	pub fn uv(&self) -> [f32; 2] {
+		self.uv
+	}
 }
 
 impl From<&TexturedVertex> for Vertex {
@@ -0,0 +1,28 @@
+use cgmath::Matrix4;
+use openvr::system::RawProjection;
+
+/// Builds OpenVR style projection (Y up, Z in 0..1) from raw frustum tangents.
+///
+/// Unlike `System::projection_matrix`, allows placing the far plane at infinity (`far` of `None`)
+/// and reversing the depth range, so the near plane maps to 1 and the far plane to 0.
+pub fn projection_matrix(raw: &RawProjection, near: f32, far: Option<f32>, reversed: bool) -> Matrix4<f32> {
+	let idx = 1.0 / (raw.right - raw.left);
+	let idy = 1.0 / (raw.bottom - raw.top);
+	let sx = raw.right + raw.left;
+	let sy = raw.bottom + raw.top;
+	
+	let (z_scale, z_offset) = match (far, reversed) {
+		(Some(far), false) => (far / (near - far), near * far / (near - far)),
+		(None, false) => (-1.0, -near),
+		(Some(far), true) => (near / (far - near), near * far / (far - near)),
+		(None, true) => (0.0, near),
+	};
+	
+	// Column major
+	Matrix4::new(
+		2.0 * idx, 0.0,       0.0,      0.0,
+		0.0,       2.0 * idy, 0.0,      0.0,
+		sx * idx,  sy * idy,  z_scale, -1.0,
+		0.0,       0.0,       z_offset, 0.0,
+	)
+}
@@ -0,0 +1,98 @@
+pub const MIN_SCALE: f32 = 0.5;
+pub const MAX_SCALE: f32 = 2.0;
+
+// Fraction of the frame budget GPU time is kept between
+const LOW_WATERMARK: f32 = 0.70;
+const HIGH_WATERMARK: f32 = 0.90;
+// Frames to wait after each change, so the timings reflect the new resolution
+const COOLDOWN: u32 = 30;
+const SMOOTHING: f32 = 0.1;
+const STEP: f32 = 0.05;
+
+/// Picks the render scale (per axis, relative to the recommended render target size),
+/// optionally adapting it to GPU frame time so it stays within the headset's frame budget.
+pub struct ResolutionScaler {
+	scale: f32,
+	adaptive_max: f32,
+	adaptive: bool,
+	budget_ms: f32,
+	smoothed_ms: Option<f32>,
+	cooldown: u32,
+}
+
+impl ResolutionScaler {
+	pub fn new(scale: f32, adaptive: bool, display_frequency: f32) -> ResolutionScaler {
+		let scale = clamp_scale(scale);
+		
+		ResolutionScaler {
+			scale,
+			adaptive_max: scale.max(1.0),
+			adaptive,
+			budget_ms: 1000.0 / display_frequency,
+			smoothed_ms: None,
+			cooldown: 0,
+		}
+	}
+	
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+	
+	pub fn adaptive(&self) -> bool {
+		self.adaptive
+	}
+	
+	pub fn set_adaptive(&mut self, adaptive: bool) {
+		self.adaptive = adaptive;
+		self.smoothed_ms = None;
+	}
+	
+	/// Manually overrides current scale. Adaptive mode won't go above the largest manually set scale.
+	pub fn set_scale(&mut self, scale: f32) -> f32 {
+		self.scale = clamp_scale(scale);
+		self.adaptive_max = self.adaptive_max.max(self.scale);
+		self.cooldown = COOLDOWN;
+		self.scale
+	}
+	
+	/// Feeds GPU time of the last frame. Returns new scale if it has changed.
+	pub fn update(&mut self, gpu_ms: f32) -> Option<f32> {
+		if !self.adaptive || gpu_ms <= 0.0 {
+			return None;
+		}
+		
+		let smoothed = match self.smoothed_ms {
+			Some(smoothed) => smoothed + (gpu_ms - smoothed) * SMOOTHING,
+			None => gpu_ms,
+		};
+		self.smoothed_ms = Some(smoothed);
+		
+		if self.cooldown > 0 {
+			self.cooldown -= 1;
+			return None;
+		}
+		
+		let load = smoothed / self.budget_ms;
+		let target = if load > HIGH_WATERMARK {
+			self.scale - STEP
+		} else if load < LOW_WATERMARK {
+			self.scale + STEP
+		} else {
+			return None;
+		};
+		
+		let target = target.max(MIN_SCALE).min(self.adaptive_max);
+		
+		if (target - self.scale).abs() < std::f32::EPSILON {
+			return None;
+		}
+		
+		self.scale = target;
+		self.cooldown = COOLDOWN;
+		Some(self.scale)
+	}
+}
+
+fn clamp_scale(scale: f32) -> f32 {
+	scale.max(MIN_SCALE).min(MAX_SCALE)
+}
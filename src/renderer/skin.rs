@@ -0,0 +1,43 @@
+use cgmath::{Matrix4, SquareMatrix};
+
+/// Joints of a single skin. Must match the vertex shader.
+pub const MAX_JOINTS: usize = 64;
+/// Morph targets of a single model. Must match the vertex shader.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// Joint palette and morph target weights of a drawn model, laid out like the `Skin` block of the vertex shader.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SkinUniform {
+	joints: [[[f32; 4]; 4]; MAX_JOINTS],
+	morph_weights: [f32; MAX_MORPH_TARGETS],
+}
+
+impl SkinUniform {
+	/// Joints and weights past the limits are ignored. Missing joints are identity, so rigid vertices stay in place.
+	pub fn new(joints: &[Matrix4<f32>], morph_weights: &[f32]) -> SkinUniform {
+		let mut uniform = SkinUniform {
+			joints: [Matrix4::identity().into(); MAX_JOINTS],
+			morph_weights: [0.0; MAX_MORPH_TARGETS],
+		};
+		
+		for (target, joint) in uniform.joints.iter_mut().zip(joints) {
+			*target = (*joint).into();
+		}
+		
+		for (target, weight) in uniform.morph_weights.iter_mut().zip(morph_weights) {
+			*target = *weight;
+		}
+		
+		uniform
+	}
+}
+
+/// Push constants of the model pipeline.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ModelConstants {
+	pub mpv: [[f32; 4]; 4],
+	pub morph_targets: u32,
+	pub opacity: f32,
+}
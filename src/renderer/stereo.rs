@@ -0,0 +1,219 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use err_derive::Error;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode, SamplerCreationError};
+use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, PersistentDescriptorSetError, PersistentDescriptorSetBuildError};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineCreationError};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, DrawError};
+use gif::SetParameter;
+use image::RgbaImage;
+
+use crate::shaders;
+use crate::renderer::eye::Eye;
+
+/// Time each eye is shown for in wiggle stereo.
+pub const WIGGLE_INTERVAL: Duration = Duration::from_millis(150);
+
+pub type StereoPipeline = GraphicsPipeline<
+	BufferlessDefinition,
+	Box<dyn PipelineLayoutAbstract + Send + Sync>,
+	Arc<dyn RenderPassAbstract + Send + Sync>
+>;
+
+/// How the two eye images are combined into one, for viewing without a headset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StereoMode {
+	Left,
+	Right,
+	/// Eyes next to each other, for viewing with parallel eyes.
+	Parallel,
+	/// Eyes next to each other swapped, for viewing cross-eyed.
+	CrossEye,
+	/// Left eye in the red channel, right eye in green and blue, for red/cyan glasses.
+	Anaglyph,
+	/// Left eye in even rows and right eye in odd rows, for line interlaced 3D displays.
+	Interlaced,
+	/// Alternating between the eyes every `WIGGLE_INTERVAL`.
+	Wiggle,
+}
+
+impl StereoMode {
+	pub const ALL: [StereoMode; 7] = [StereoMode::Left, StereoMode::Right, StereoMode::Parallel, StereoMode::CrossEye,
+	                                  StereoMode::Anaglyph, StereoMode::Interlaced, StereoMode::Wiggle];
+	
+	fn name(self) -> &'static str {
+		match self {
+			StereoMode::Left => "left",
+			StereoMode::Right => "right",
+			StereoMode::Parallel => "parallel",
+			StereoMode::CrossEye => "cross-eye",
+			StereoMode::Anaglyph => "anaglyph",
+			StereoMode::Interlaced => "interlaced",
+			StereoMode::Wiggle => "wiggle",
+		}
+	}
+	
+	pub fn next(self) -> StereoMode {
+		let index = StereoMode::ALL.iter().position(|&mode| mode == self).unwrap();
+		StereoMode::ALL[(index + 1) % StereoMode::ALL.len()]
+	}
+	
+	/// Width of the combined image relative to a single eye.
+	pub fn width(self) -> u32 {
+		match self {
+			StereoMode::Parallel | StereoMode::CrossEye => 2,
+			_ => 1,
+		}
+	}
+	
+	/// Still images making up the mode, shown one after another every `WIGGLE_INTERVAL`.
+	pub fn frames(self) -> Vec<StereoMode> {
+		match self {
+			StereoMode::Wiggle => vec![StereoMode::Left, StereoMode::Right],
+			mode => vec![mode],
+		}
+	}
+	
+	/// Extension of exported images, wiggle stereo is animated.
+	pub fn image_extension(self) -> &'static str {
+		match self {
+			StereoMode::Wiggle => "gif",
+			_ => "png",
+		}
+	}
+	
+	/// Still image shown `elapsed` time after the mode started.
+	pub fn frame_at(self, elapsed: Duration) -> StereoMode {
+		let frames = self.frames();
+		let index = (elapsed.as_millis() / WIGGLE_INTERVAL.as_millis()) as usize % frames.len();
+		frames[index]
+	}
+}
+
+impl fmt::Display for StereoMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for StereoMode {
+	type Err = String;
+	
+	fn from_str(s: &str) -> Result<StereoMode, String> {
+		StereoMode::ALL.iter()
+		               .find(|mode| mode.name() == s)
+		               .cloned()
+		               .ok_or_else(|| format!("Unknown stereo mode: {}, expected one of left, right, parallel, cross-eye, anaglyph, interlaced, wiggle", s))
+	}
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct StereoParams {
+	uv_scale: [f32; 2],
+	mode: u32,
+}
+
+/// Draws the eye images combined according to a stereo mode, filling the viewport.
+pub struct StereoCompositor {
+	pipeline: Arc<StereoPipeline>,
+	sampler: Arc<Sampler>,
+}
+
+impl StereoCompositor {
+	pub fn new(device: &Arc<Device>, render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>) -> Result<StereoCompositor, StereoError> {
+		let vs = shaders::fullscreen_vert::Shader::load(device.clone()).unwrap();
+		let fs = shaders::stereo_frag::Shader::load(device.clone()).unwrap();
+		
+		let pipeline = Arc::new(
+			GraphicsPipeline::start()
+			                 .vertex_input(BufferlessDefinition)
+			                 .vertex_shader(vs.main_entry_point(), ())
+			                 .viewports_dynamic_scissors_irrelevant(1)
+			                 .fragment_shader(fs.main_entry_point(), ())
+			                 .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+			                 .build(device.clone())?
+		);
+		
+		let sampler = Sampler::new(device.clone(), Filter::Linear, Filter::Linear, MipmapMode::Nearest,
+		                           SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge,
+		                           0.0, 1.0, 0.0, 0.0)?;
+		
+		Ok(StereoCompositor {
+			pipeline,
+			sampler,
+		})
+	}
+	
+	/// Records drawing of a still `mode` inside a render pass. `render_size` is the part of the eye images rendered to.
+	pub fn draw(&self,
+	            command_buffer: AutoCommandBufferBuilder,
+	            dynamic_state: &DynamicState,
+	            eyes: &(Eye, Eye),
+	            render_size: (u32, u32),
+	            mode: StereoMode)
+	            -> Result<AutoCommandBufferBuilder, StereoError> {
+		let set = Arc::new(
+			PersistentDescriptorSet::start(self.pipeline.descriptor_set_layout(0).ok_or(StereoError::NoLayout)?.clone())
+			                        .add_sampled_image(eyes.0.image.clone(), self.sampler.clone())?
+			                        .add_sampled_image(eyes.1.image.clone(), self.sampler.clone())?
+			                        .build()?
+		);
+		
+		let eye_size = eyes.0.size();
+		let params = StereoParams {
+			uv_scale: [render_size.0 as f32 / eye_size.0 as f32, render_size.1 as f32 / eye_size.1 as f32],
+			mode: mode as u32,
+		};
+		
+		Ok(command_buffer.draw(self.pipeline.clone(), dynamic_state, BufferlessVertices { vertices: 3, instances: 1 }, set, params)?)
+	}
+}
+
+/// Largest viewport with the aspect ratio of `content`, centered in the target.
+pub fn fit_viewport(target: [u32; 2], content: (u32, u32)) -> DynamicState {
+	let scale = (target[0] as f32 / content.0.max(1) as f32).min(target[1] as f32 / content.1.max(1) as f32);
+	let dimensions = [content.0 as f32 * scale, content.1 as f32 * scale];
+	
+	DynamicState {
+		viewports: Some(vec![Viewport { origin: [(target[0] as f32 - dimensions[0]) / 2.0, (target[1] as f32 - dimensions[1]) / 2.0],
+		                                dimensions,
+		                                depth_range: 0.0 .. 1.0 }]),
+		..DynamicState::none()
+	}
+}
+
+/// Saves frames as a looping GIF, each shown for `WIGGLE_INTERVAL`.
+pub fn save_animation(path: &Path, frames: Vec<RgbaImage>) -> io::Result<()> {
+	let (width, height) = frames.first().map_or((0, 0), RgbaImage::dimensions);
+	let mut encoder = gif::Encoder::new(File::create(path)?, width as u16, height as u16, &[])?;
+	encoder.set(gif::Repeat::Infinite)?;
+	
+	for mut image in frames {
+		let mut frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut *image, 10);
+		frame.delay = (WIGGLE_INTERVAL.as_millis() / 10) as u16;
+		encoder.write_frame(&frame)?;
+	}
+	
+	Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum StereoError {
+	#[error(display = "Pipeline doesn't have layout set 0")] NoLayout,
+	#[error(display = "{}", _0)] GraphicsPipelineCreationError(#[error(source)] GraphicsPipelineCreationError),
+	#[error(display = "{}", _0)] SamplerCreationError(#[error(source)] SamplerCreationError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetError(#[error(source)] PersistentDescriptorSetError),
+	#[error(display = "{}", _0)] PersistentDescriptorSetBuildError(#[error(source)] PersistentDescriptorSetBuildError),
+	#[error(display = "{}", _0)] DrawError(#[error(source)] DrawError),
+}
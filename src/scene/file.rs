@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use err_derive::Error;
+use cgmath::{Matrix3, Matrix4, Vector3, Quaternion, Decomposed, Deg, Rad, InnerSpace, One, Zero};
+use obj::{load_obj, ObjError, TexturedVertex, Obj};
+use image::ImageError;
+
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture, Vertex};
+use crate::renderer::lod::{LodModel, LodLevel, LodThreshold};
+use crate::behaviour::{Behaviours, Spin, Follow, Billboard, Animate, BehaviourError, load_keyframes};
+use crate::animation::{Clip, ClipPlayer};
+use crate::eye_rotation::{from_angles, to_angles};
+use super::Scene;
+use super::import::{load_gltf, ImportError};
+
+/// Color of models declared without a texture.
+const UNTEXTURED_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+pub type Transform = Decomposed<Vector3<f32>, Quaternion<f32>>;
+
+/// Node declared in a scene file.
+pub struct NodeEntry {
+	pub name: String,
+	/// Name of the parent node, root if not given. Nodes declared earlier and nodes of the application, like `scene` or `left_hand`, can be used.
+	pub parent: Option<String>,
+	/// OBJ model, or glTF file whose scene is added as children of the node.
+	pub model: Option<PathBuf>,
+	/// Less detailed OBJ models, each used once the previous level's threshold is passed.
+	pub lods: Vec<(PathBuf, LodThreshold)>,
+	/// Texture of the model and all its levels of detail.
+	pub texture: Option<PathBuf>,
+	/// Transform relative to the parent node.
+	pub transform: Transform,
+}
+
+pub enum BehaviourEntry {
+	/// Speed in degrees per second.
+	Spin { node: String, axis: Vector3<f32>, speed: f32 },
+	/// Node's own transform is used as the offset from the target.
+	Follow { node: String, target: String },
+	Billboard { node: String },
+	/// Plays an animation of the glTF model loaded into the node, the first one if no clip is named.
+	Animate { node: String, clip: Option<String>, looping: bool },
+	/// Moves the node through transforms loaded from a keyframe file.
+	Keyframes { node: String, path: PathBuf, looping: bool },
+}
+
+/// Nodes, behaviours and scripts added on top of the built-in scene.
+pub struct SceneFile {
+	pub nodes: Vec<NodeEntry>,
+	pub behaviours: Vec<BehaviourEntry>,
+	/// Nodes which can be picked up with the controllers.
+	pub grabbable: Vec<String>,
+	/// Script files, run in order every frame.
+	pub scripts: Vec<PathBuf>,
+}
+
+impl SceneFile {
+	/// Loads a scene file. Each line holds one of:
+	/// - `node NAME [parent=NAME] [model=FILE.obj|FILE.gltf|FILE.glb] [lod=FILE.obj:DISTANCE|SIZE%...] [texture=FILE.png] [position=X:Y:Z] [rotation=PITCH:YAW:ROLL] [scale=FACTOR]`
+	/// - `spin NODE X:Y:Z DEGREES/S`
+	/// - `follow NODE TARGET`
+	/// - `billboard NODE`
+	/// - `animate NODE [CLIP] [once]`
+	/// - `keyframes NODE FILE [once]`
+	/// - `grabbable NODE`
+	/// - `script FILE`
+	///
+	/// Positions are in meters, angles in degrees and paths relative to the scene file.
+	/// Empty lines and lines starting with `#` are ignored.
+	pub fn load(path: &Path) -> Result<SceneFile, SceneFileError> {
+		let source = fs::read_to_string(path)?;
+		let dir = path.parent().unwrap_or_else(|| Path::new(""));
+		let mut file = SceneFile {
+			nodes: Vec::new(),
+			behaviours: Vec::new(),
+			grabbable: Vec::new(),
+			scripts: Vec::new(),
+		};
+		
+		for (number, line) in source.lines().enumerate() {
+			let line = line.trim();
+			let error = |message: String| SceneFileError::InvalidLine(number + 1, message);
+			
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			
+			let parts: Vec<&str> = line.split_whitespace().collect();
+			let arguments = |count: usize, usage: &str| if parts.len() == count + 1 {
+				Ok(&parts[1..])
+			} else {
+				Err(error(format!("Expected {} {}", parts[0], usage)))
+			};
+			
+			match parts[0] {
+				"node" => file.nodes.push(parse_node(&parts[1..], dir).map_err(error)?),
+				"spin" => {
+					let arguments = arguments(3, "NODE X:Y:Z DEGREES/S")?;
+					let axis = parse_vector(arguments[1]).map_err(error)?;
+					if axis.is_zero() {
+						return Err(error("Spin axis can't be zero".to_string()));
+					}
+					
+					file.behaviours.push(BehaviourEntry::Spin {
+						node: arguments[0].to_string(),
+						axis,
+						speed: arguments[2].parse().map_err(|_| error(format!("Invalid speed: {}", arguments[2])))?,
+					});
+				},
+				"follow" => {
+					let arguments = arguments(2, "NODE TARGET")?;
+					file.behaviours.push(BehaviourEntry::Follow { node: arguments[0].to_string(), target: arguments[1].to_string() });
+				},
+				"billboard" => {
+					let arguments = arguments(1, "NODE")?;
+					file.behaviours.push(BehaviourEntry::Billboard { node: arguments[0].to_string() });
+				},
+				"animate" => {
+					let (node, rest) = parts[1..].split_first().ok_or_else(|| error("Expected animate NODE [CLIP] [once]".to_string()))?;
+					let looping = rest.last() != Some(&"once");
+					let rest = if looping { rest } else { &rest[..rest.len() - 1] };
+					
+					file.behaviours.push(BehaviourEntry::Animate {
+						node: node.to_string(),
+						clip: match rest {
+							[] => None,
+							[clip] => Some(clip.to_string()),
+							_ => return Err(error("Expected animate NODE [CLIP] [once]".to_string())),
+						},
+						looping,
+					});
+				},
+				"keyframes" => {
+					let (node, path, looping) = match parts[1..] {
+						[node, path] => (node, path, true),
+						[node, path, "once"] => (node, path, false),
+						_ => return Err(error("Expected keyframes NODE FILE [once]".to_string())),
+					};
+					
+					file.behaviours.push(BehaviourEntry::Keyframes { node: node.to_string(), path: dir.join(path), looping });
+				},
+				"grabbable" => {
+					let arguments = arguments(1, "NODE")?;
+					file.grabbable.push(arguments[0].to_string());
+				},
+				"script" => {
+					let arguments = arguments(1, "FILE")?;
+					file.scripts.push(dir.join(arguments[0]));
+				},
+				other => return Err(error(format!("Unknown entry: {}", other))),
+			}
+		}
+		
+		Ok(file)
+	}
+	
+	/// Adds declared nodes to the scene and attaches their behaviours.
+	pub fn instantiate(&self, scene: &mut Scene, behaviours: &mut Behaviours, renderer: &Renderer) -> Result<(), SceneFileError> {
+		let find = |scene: &Scene, name: &str| scene.find(name).ok_or_else(|| SceneFileError::UnknownNode(name.to_string()));
+		let mut clips: HashMap<&str, Vec<Clip>> = HashMap::new();
+		
+		for entry in &self.nodes {
+			let parent = match &entry.parent {
+				Some(parent) => find(scene, parent)?,
+				None => scene.root(),
+			};
+			
+			match &entry.model {
+				Some(path) if is_gltf(path) => {
+					let node = scene.add(parent, entry.name.clone(), None, entry.transform.into());
+					clips.insert(&entry.name, load_gltf(path, scene, node, renderer)?);
+				},
+				Some(path) => {
+					let texture = match &entry.texture {
+						Some(texture) => Texture::new(&image::open(texture)?, renderer)?,
+						None => Texture::from_color(UNTEXTURED_COLOR, renderer)?,
+					};
+					
+					let mut levels = vec![LodLevel { model: load_model(path, texture.clone(), renderer)?,
+					                                 threshold: LodThreshold::Distance(std::f32::INFINITY) }];
+					
+					for (path, threshold) in &entry.lods {
+						levels.last_mut().unwrap().threshold = *threshold;
+						levels.push(LodLevel { model: load_model(path, texture.clone(), renderer)?,
+						                       threshold: LodThreshold::Distance(std::f32::INFINITY) });
+					}
+					
+					scene.add(parent, entry.name.clone(), Some(LodModel::from_levels(levels)), entry.transform.into());
+				},
+				None => {
+					scene.add(parent, entry.name.clone(), None, entry.transform.into());
+				},
+			}
+		}
+		
+		for entry in &self.behaviours {
+			match entry {
+				BehaviourEntry::Spin { node, axis, speed } => {
+					behaviours.add(find(scene, node)?, Spin { axis: *axis, speed: Rad::from(Deg(*speed)).0 });
+				},
+				BehaviourEntry::Follow { node, target } => {
+					let node = find(scene, node)?;
+					let offset = scene.get(node).unwrap().transform;
+					behaviours.add(node, Follow { target: find(scene, target)?, offset });
+				},
+				BehaviourEntry::Billboard { node } => {
+					behaviours.add(find(scene, node)?, Billboard);
+				},
+				BehaviourEntry::Animate { node, clip, looping } => {
+					let available = clips.get(node.as_str()).map_or(&[][..], Vec::as_slice);
+					let found = match clip {
+						Some(clip) => available.iter().find(|available| &available.name == clip),
+						None => available.first(),
+					};
+					
+					let found = found.ok_or_else(|| SceneFileError::UnknownClip(node.clone(), clip.clone().unwrap_or_else(|| "to play".to_string())))?;
+					behaviours.add(find(scene, node)?, ClipPlayer::new(found.clone(), *looping));
+				},
+				BehaviourEntry::Keyframes { node, path, looping } => {
+					behaviours.add(find(scene, node)?, Animate::new(load_keyframes(path)?, *looping));
+				},
+			}
+		}
+		
+		for name in &self.grabbable {
+			let node = find(scene, name)?;
+			scene.get_mut(node).unwrap().grabbable = true;
+		}
+		
+		Ok(())
+	}
+	
+	/// Replaces position, rotation and scale of a node declared in a scene file, keeping the rest of the file.
+	/// Transform is relative to the node's parent and shouldn't contain non-uniform scale. Returns whether the node was found.
+	pub fn save_transform(path: &Path, name: &str, transform: &Matrix4<f32>) -> Result<bool, SceneFileError> {
+		let source = fs::read_to_string(path)?;
+		let mut found = false;
+		
+		let mut lines: Vec<String> = source.lines()
+		                                   .map(|line| {
+			                                   let parts: Vec<&str> = line.split_whitespace().collect();
+			                                   if found || parts.len() < 2 || parts[0] != "node" || parts[1] != name {
+				                                   return line.to_string();
+			                                   }
+			
+			                                   found = true;
+			                                   let mut parts: Vec<String> = parts.iter()
+			                                                                     .filter(|part| !["position=", "rotation=", "scale="].iter().any(|key| part.starts_with(key)))
+			                                                                     .map(|part| part.to_string())
+			                                                                     .collect();
+			                                   parts.extend(format_transform(transform));
+			                                   parts.join(" ")
+		                                   })
+		                                   .collect();
+		
+		if found {
+			if source.ends_with('\n') {
+				lines.push(String::new());
+			}
+			fs::write(path, lines.join("\n"))?;
+		}
+		
+		Ok(found)
+	}
+}
+
+/// Node properties of a transform, in the format read by `parse_node`.
+fn format_transform(transform: &Matrix4<f32>) -> Vec<String> {
+	let scale = transform.x.truncate().magnitude();
+	let axis = |column: cgmath::Vector4<f32>| column.truncate() / scale.max(std::f32::EPSILON);
+	let rotation = Quaternion::from(Matrix3::from_cols(axis(transform.x), axis(transform.y), axis(transform.z))).normalize();
+	let position = transform.w.truncate();
+	let angles = to_angles(rotation).map(|angle| Deg::from(Rad(angle)).0);
+	
+	vec![
+		format!("position={}:{}:{}", round(position.x, 3), round(position.y, 3), round(position.z, 3)),
+		format!("rotation={}:{}:{}", round(angles.x, 1), round(angles.y, 1), round(angles.z, 1)),
+		format!("scale={}", round(scale, 3)),
+	]
+}
+
+/// Rounds to given number of decimals, so saved values stay readable.
+fn round(value: f32, decimals: i32) -> f32 {
+	let factor = 10f32.powi(decimals);
+	(value * factor).round() / factor + 0.0
+}
+
+fn parse_node(arguments: &[&str], dir: &Path) -> Result<NodeEntry, String> {
+	let name = arguments.first().ok_or_else(|| "Expected node NAME [KEY=VALUE...]".to_string())?;
+	let mut entry = NodeEntry {
+		name: name.to_string(),
+		parent: None,
+		model: None,
+		lods: Vec::new(),
+		texture: None,
+		transform: Decomposed { scale: 1.0, rot: Quaternion::one(), disp: Vector3::zero() },
+	};
+	
+	for argument in &arguments[1..] {
+		let mut parts = argument.splitn(2, '=');
+		let key = parts.next().unwrap();
+		let value = parts.next().ok_or_else(|| format!("Expected KEY=VALUE, got: {}", argument))?;
+		
+		match key {
+			"parent" => entry.parent = Some(value.to_string()),
+			"model" => entry.model = Some(dir.join(value)),
+			"lod" => {
+				let split = value.rfind(':').ok_or_else(|| format!("Invalid LOD: {}, expected FILE.obj:DISTANCE or FILE.obj:SIZE%", value))?;
+				entry.lods.push((dir.join(&value[..split]), value[split + 1..].parse()?));
+			},
+			"texture" => entry.texture = Some(dir.join(value)),
+			"position" => entry.transform.disp = parse_vector(value)?,
+			"rotation" => entry.transform.rot = from_angles(parse_vector(value)?.map(|angle| Rad::from(Deg(angle)).0)),
+			"scale" => entry.transform.scale = value.parse().map_err(|_| format!("Invalid scale: {}", value))?,
+			_ => return Err(format!("Unknown node property: {}", key)),
+		}
+	}
+	
+	if !entry.lods.is_empty() && entry.model.as_deref().map_or(true, is_gltf) {
+		return Err("Levels of detail require an OBJ model".to_string());
+	}
+	
+	Ok(entry)
+}
+
+fn parse_vector(arg: &str) -> Result<Vector3<f32>, String> {
+	let values = arg.split(':')
+	                .map(str::parse::<f32>)
+	                .collect::<Result<Vec<_>, _>>()
+	                .map_err(|err| format!("Invalid vector: {}, {}", arg, err))?;
+	
+	match values[..] {
+		[x, y, z] => Ok(Vector3::new(x, y, z)),
+		_ => Err(format!("Invalid vector: {}, expected X:Y:Z", arg)),
+	}
+}
+
+fn is_gltf(path: &Path) -> bool {
+	match path.extension().and_then(|extension| extension.to_str()) {
+		Some(extension) => extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb"),
+		None => false,
+	}
+}
+
+fn load_model(path: &Path, texture: Texture, renderer: &Renderer) -> Result<Model, SceneFileError> {
+	let obj: Obj<TexturedVertex, u16> = load_obj(BufReader::new(File::open(path)?))?;
+	let vertices: Vec<Vertex> = obj.vertices.iter().map(Into::into).collect();
+	
+	Ok(Model::with_texture(&vertices, &obj.indices, texture, renderer)?)
+}
+
+#[derive(Debug, Error)]
+pub enum SceneFileError {
+	#[error(display = "Invalid scene entry on line {}: {}", _0, _1)] InvalidLine(usize, String),
+	#[error(display = "Unknown scene node: {}", _0)] UnknownNode(String),
+	#[error(display = "Node {} has no animation {}", _0, _1)] UnknownClip(String, String),
+	#[error(display = "{}", _0)] IoError(#[error(source)] std::io::Error),
+	#[error(display = "{}", _0)] ObjError(#[error(source)] ObjError),
+	#[error(display = "{}", _0)] ImageError(#[error(source)] ImageError),
+	#[error(display = "{}", _0)] ModelError(#[error(source)] ModelError),
+	#[error(display = "{}", _0)] ImportError(#[error(source)] ImportError),
+	#[error(display = "{}", _0)] BehaviourError(#[error(source)] BehaviourError),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	/// Writes the source to a temporary file, unique per test, and passes its path.
+	fn with_file<T>(name: &str, source: &str, test: impl FnOnce(&Path) -> T) -> T {
+		let path = std::env::temp_dir().join(format!("scene-file-{}-{}.txt", std::process::id(), name));
+		fs::write(&path, source).unwrap();
+		let result = test(&path);
+		fs::remove_file(&path).unwrap();
+		result
+	}
+	
+	fn invalid_line(source: &str) -> Option<usize> {
+		match with_file("invalid", source, SceneFile::load) {
+			Err(SceneFileError::InvalidLine(line, _)) => Some(line),
+			_ => None,
+		}
+	}
+	
+	#[test]
+	fn invalid_lines() {
+		assert_eq!(invalid_line("# comment\n\nnode box\nspin box 0:0:0 10\n"), Some(4));
+		assert_eq!(invalid_line("node box\nteleport box\n"), Some(2));
+		assert_eq!(invalid_line("node box color=red\n"), Some(1));
+		assert_eq!(invalid_line("node box lod=low.obj:5\n"), Some(1));
+		assert_eq!(invalid_line("node box model=box.gltf lod=low.obj:5\n"), Some(1));
+		assert_eq!(invalid_line("node box\n\n  \nfollow box\n"), Some(4));
+		assert_eq!(invalid_line("animate\n"), Some(1));
+		assert_eq!(invalid_line("node box\nanimate box walk run once\n"), Some(2));
+		assert_eq!(invalid_line("keyframes box\n"), Some(1));
+		assert_eq!(invalid_line("keyframes box path.txt twice\n"), Some(1));
+		assert_eq!(invalid_line("# comment\nnode box\n"), None);
+	}
+	
+	#[test]
+	fn animate() {
+		let file = with_file("animate", "animate a\nanimate b walk\nanimate c once\nanimate d walk once\n", SceneFile::load).unwrap();
+		let entries: Vec<_> = file.behaviours.iter().map(|entry| match entry {
+			BehaviourEntry::Animate { node, clip, looping } => (node.as_str(), clip.as_deref(), *looping),
+			_ => panic!("Expected an animate entry"),
+		}).collect();
+		
+		assert_eq!(entries, [("a", None, true), ("b", Some("walk"), true), ("c", None, false), ("d", Some("walk"), false)]);
+	}
+	
+	#[test]
+	fn keyframes() {
+		with_file("keyframes", "keyframes a path.txt\nkeyframes b path.txt once\n", |path| {
+			let file = SceneFile::load(path).unwrap();
+			let keyframes = path.parent().unwrap().join("path.txt");
+			let entries: Vec<_> = file.behaviours.iter().map(|entry| match entry {
+				BehaviourEntry::Keyframes { node, path, looping } => (node.as_str(), path.clone(), *looping),
+				_ => panic!("Expected a keyframes entry"),
+			}).collect();
+			
+			assert_eq!(entries, [("a", keyframes.clone(), true), ("b", keyframes, false)]);
+		});
+	}
+	
+	#[test]
+	fn save_transform() {
+		let source = "# boxes\nnode box model=box.obj position=1:2:3 scale=4\nnode lid parent=box\n";
+		let transform = Decomposed {
+			scale: 2.0,
+			rot: from_angles(Vector3::new(10.0, 30.0, 0.0).map(|angle| Rad::from(Deg(angle)).0)),
+			disp: Vector3::new(0.5, 1.0, -2.0),
+		};
+		
+		let saved = with_file("save", source, |path| {
+			assert!(SceneFile::save_transform(path, "box", &transform.into()).unwrap());
+			assert!(!SceneFile::save_transform(path, "missing", &transform.into()).unwrap());
+			fs::read_to_string(path).unwrap()
+		});
+		let lines: Vec<&str> = saved.lines().collect();
+		
+		assert!(saved.ends_with('\n'));
+		assert_eq!(lines.len(), 3);
+		assert_eq!(lines[0], "# boxes");
+		assert_eq!(lines[2], "node lid parent=box");
+		
+		let parts: Vec<&str> = lines[1].split_whitespace().collect();
+		assert_eq!(&parts[..3], ["node", "box", "model=box.obj"]);
+		
+		let entry = parse_node(&parts[1..], Path::new("")).unwrap();
+		assert_eq!(entry.model, Some(PathBuf::from("box.obj")));
+		assert!((entry.transform.disp - transform.disp).magnitude() < 1e-3);
+		assert!((entry.transform.scale - transform.scale).abs() < 1e-3);
+		assert!(entry.transform.rot.dot(transform.rot).abs() > 0.9999);
+	}
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::path::Path;
+use err_derive::Error;
+use cgmath::{Matrix4, Vector3, Quaternion, SquareMatrix};
+use image::{DynamicImage, ImageBuffer};
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+
+use crate::renderer::Renderer;
+use crate::renderer::model::{Model, ModelError, Texture, Vertex};
+use crate::renderer::lod::LodModel;
+use crate::renderer::skin::MAX_JOINTS;
+use crate::animation::{self, Clip, Channel, Values, Trs};
+use super::{Scene, NodeId, Skin};
+
+/// Loads the default scene of a glTF file as children of given node. Meshes with multiple primitives get a child node
+/// named `NODE.N` for every primitive after the first one. Returns animations of the file, targeting the added nodes.
+pub fn load_gltf(path: &Path, scene: &mut Scene, parent: NodeId, renderer: &Renderer) -> Result<Vec<Clip>, ImportError> {
+	let (document, buffers, images) = gltf::import(path)?;
+	let mut importer = Importer {
+		buffers: &buffers,
+		images: &images,
+		renderer,
+		textures: HashMap::new(),
+		nodes: HashMap::new(),
+		meshes: HashMap::new(),
+	};
+	
+	let roots: Vec<gltf::Node> = document.default_scene()
+	                                     .or_else(|| document.scenes().next())
+	                                     .map(|root| root.nodes().collect())
+	                                     .unwrap_or_default();
+	
+	for node in roots {
+		importer.add_node(node, scene, parent)?;
+	}
+	
+	// Joints can be anywhere in the hierarchy, so skins are bound once all nodes exist
+	for node in document.nodes() {
+		if let Some(skin) = node.skin() {
+			importer.bind_skin(&node, &skin, scene)?;
+		}
+	}
+	
+	Ok(document.animations().map(|animation| importer.clip(animation)).collect())
+}
+
+struct Importer<'a> {
+	buffers: &'a [gltf::buffer::Data],
+	images: &'a [gltf::image::Data],
+	renderer: &'a Renderer,
+	/// Uploaded textures by glTF image index.
+	textures: HashMap<usize, Texture>,
+	/// Scene nodes by glTF node index.
+	nodes: HashMap<usize, NodeId>,
+	/// Scene nodes holding the primitives of a glTF node's mesh, by glTF node index.
+	meshes: HashMap<usize, Vec<NodeId>>,
+}
+
+impl<'a> Importer<'a> {
+	fn buffer(&self, buffer: gltf::Buffer) -> Option<&'a [u8]> {
+		self.buffers.get(buffer.index()).map(|data| &**data)
+	}
+	
+	fn add_node(&mut self, node: gltf::Node, scene: &mut Scene, parent: NodeId) -> Result<(), ImportError> {
+		let name = node.name().map_or_else(|| format!("node{}", node.index()), str::to_string);
+		let id = scene.add(parent, name.clone(), None, node.transform().matrix().into());
+		self.nodes.insert(node.index(), id);
+		
+		if let Some(mesh) = node.mesh() {
+			let weights = node.weights().or_else(|| mesh.weights());
+			let joint_count = node.skin().map(|skin| skin.joints().count());
+			let mut targets = Vec::new();
+			
+			for primitive in mesh.primitives() {
+				let model = match self.primitive(&primitive, joint_count)? {
+					Some(model) => model,
+					None => continue,
+				};
+				
+				let morph_targets = model.morph_targets as usize;
+				let target = if targets.is_empty() {
+					id
+				} else {
+					scene.add(id, format!("{}.{}", name, targets.len()), None, Matrix4::identity())
+				};
+				
+				let target_node = scene.get_mut(target).unwrap();
+				target_node.model = Some(LodModel::new(model));
+				target_node.morph_weights = weights.map_or_else(|| vec![0.0; morph_targets], <[f32]>::to_vec);
+				targets.push(target);
+			}
+			
+			self.meshes.insert(node.index(), targets);
+		}
+		
+		for child in node.children() {
+			self.add_node(child, scene, id)?;
+		}
+		
+		Ok(())
+	}
+	
+	/// Returns `None` for primitives which can't be drawn. Joints are only read if the mesh's node has a skin
+	/// with `joint_count` joints.
+	fn primitive(&mut self, primitive: &gltf::Primitive, joint_count: Option<usize>) -> Result<Option<Model>, ImportError> {
+		if primitive.mode() != Mode::Triangles {
+			eprintln!("Skipping glTF primitive with unsupported mode {:?}", primitive.mode());
+			return Ok(None);
+		}
+		
+		let reader = primitive.reader(|buffer| self.buffer(buffer));
+		let positions: Vec<[f32; 3]> = match reader.read_positions() {
+			Some(positions) => positions.collect(),
+			None => return Ok(None),
+		};
+		
+		if positions.len() > std::u16::MAX as usize + 1 {
+			return Err(ImportError::TooManyVertices(positions.len()));
+		}
+		
+		let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).map_or_else(Vec::new, |uvs| uvs.into_f32().collect());
+		let joints: Vec<[u16; 4]> = match (joint_count, reader.read_joints(0)) {
+			(Some(_), Some(joints)) => joints.into_u16().collect(),
+			_ => Vec::new(),
+		};
+		let weights: Vec<[f32; 4]> = reader.read_weights(0).map_or_else(Vec::new, |weights| weights.into_f32().collect());
+		
+		let used_joints = joints.iter()
+		                        .zip(&weights)
+		                        .flat_map(|(joints, weights)| joints.iter().zip(weights))
+		                        .filter(|(_, &weight)| weight != 0.0)
+		                        .map(|(&joint, _)| joint as usize);
+		
+		if let (Some(count), Some(index)) = (joint_count, used_joints.max()) {
+			if index >= count {
+				return Err(ImportError::InvalidJoint(index, count));
+			}
+		}
+		
+		let vertices: Vec<Vertex> = positions.iter()
+		                                     .enumerate()
+		                                     .map(|(index, &[x, y, z])| {
+			                                     let [u, v] = uvs.get(index).cloned().unwrap_or_default();
+			                                     let vertex = Vertex::new(x, y, z, u, v);
+			
+			                                     match (joints.get(index), weights.get(index)) {
+				                                     (Some(joints), Some(&weights)) => {
+					                                     // Unweighted influences may hold any index, they're pointed at the first joint
+					                                     let joint = |i: usize| if weights[i] != 0.0 { joints[i] as u32 } else { 0 };
+					                                     vertex.with_skin([joint(0), joint(1), joint(2), joint(3)], weights)
+				                                     },
+				                                     _ => vertex,
+			                                     }
+		                                     })
+		                                     .collect();
+		
+		let indices: Vec<u16> = match reader.read_indices() {
+			Some(indices) => indices.into_u32().map(|index| index as u16).collect(),
+			None => (0..vertices.len()).map(|index| index as u16).collect(),
+		};
+		
+		let targets: Vec<Vec<[f32; 3]>> = reader.read_morph_targets()
+		                                        .map(|(positions, _, _)| positions.map_or_else(|| vec![[0.0; 3]; vertices.len()], Iterator::collect))
+		                                        .collect();
+		
+		let texture = self.texture(primitive.material())?;
+		
+		Ok(Some(Model::with_morph_targets(&vertices, &indices, texture, &targets, self.renderer)?))
+	}
+	
+	/// Base color texture of the material, or its base color if it has none.
+	fn texture(&mut self, material: gltf::Material) -> Result<Texture, ImportError> {
+		let pbr = material.pbr_metallic_roughness();
+		let source = pbr.base_color_texture().map(|info| info.texture().source().index());
+		
+		if let Some(texture) = source.and_then(|source| self.textures.get(&source)) {
+			return Ok(texture.clone());
+		}
+		
+		match source.and_then(|source| self.images.get(source)).and_then(convert_image) {
+			Some(image) => {
+				let texture = Texture::new(&image, self.renderer)?;
+				self.textures.insert(source.unwrap(), texture.clone());
+				Ok(texture)
+			},
+			None => {
+				let color = pbr.base_color_factor();
+				let channel = |value: f32| (value.max(0.0).min(1.0) * 255.0) as u8;
+				Ok(Texture::from_color([channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3])], self.renderer)?)
+			},
+		}
+	}
+	
+	fn bind_skin(&self, node: &gltf::Node, skin: &gltf::Skin, scene: &mut Scene) -> Result<(), ImportError> {
+		let targets = match self.meshes.get(&node.index()) {
+			Some(targets) => targets,
+			None => return Ok(()),
+		};
+		
+		let joints = skin.joints()
+		                 .map(|joint| self.nodes.get(&joint.index()).cloned().ok_or(ImportError::MissingJoint))
+		                 .collect::<Result<Vec<_>, _>>()?;
+		
+		if joints.len() > MAX_JOINTS {
+			return Err(ImportError::TooManyJoints(joints.len()));
+		}
+		
+		let inverse_bind_matrices = match skin.reader(|buffer| self.buffer(buffer)).read_inverse_bind_matrices() {
+			Some(matrices) => matrices.map(Matrix4::from).collect(),
+			None => vec![Matrix4::identity(); joints.len()],
+		};
+		
+		let skin = Skin { joints, inverse_bind_matrices };
+		
+		for &target in targets {
+			scene.get_mut(target).unwrap().skin = Some(skin.clone());
+		}
+		
+		Ok(())
+	}
+	
+	/// Skips channels targeting nodes outside of the scene and channels with invalid keyframes.
+	fn clip(&self, animation: gltf::Animation) -> Clip {
+		let name = animation.name().map_or_else(|| format!("animation{}", animation.index()), str::to_string);
+		let mut channels = Vec::new();
+		let mut rest = HashMap::new();
+		
+		for channel in animation.channels() {
+			let target = channel.target().node();
+			let node = match self.nodes.get(&target.index()) {
+				Some(&node) => node,
+				None => continue,
+			};
+			
+			let reader = channel.reader(|buffer| self.buffer(buffer));
+			let times: Vec<f32> = match reader.read_inputs() {
+				Some(times) => times.collect(),
+				None => continue,
+			};
+			
+			let values = match reader.read_outputs() {
+				Some(ReadOutputs::Translations(values)) => Values::Translation(values.map(Vector3::from).collect()),
+				Some(ReadOutputs::Rotations(values)) => Values::Rotation(values.into_f32().map(|[x, y, z, w]| Quaternion::new(w, x, y, z)).collect()),
+				Some(ReadOutputs::Scales(values)) => Values::Scale(values.map(Vector3::from).collect()),
+				Some(ReadOutputs::MorphTargetWeights(values)) => Values::MorphWeights(values.into_f32().collect()),
+				None => continue,
+			};
+			
+			let interpolation = match channel.sampler().interpolation() {
+				gltf::animation::Interpolation::Step => animation::Interpolation::Step,
+				gltf::animation::Interpolation::Linear => animation::Interpolation::Linear,
+				gltf::animation::Interpolation::CubicSpline => animation::Interpolation::CubicSpline,
+			};
+			
+			let (translation, [x, y, z, w], scale) = target.transform().decomposed();
+			rest.insert(node, Trs { translation: translation.into(), rotation: Quaternion::new(w, x, y, z), scale: scale.into() });
+			
+			// Morph weights apply to every primitive of the node's mesh
+			let nodes = match values {
+				Values::MorphWeights(_) => self.meshes.get(&target.index()).cloned().unwrap_or_default(),
+				_ => vec![node],
+			};
+			
+			for node in nodes {
+				let channel = Channel { node, times: times.clone(), interpolation, values: values.clone() };
+				
+				if channel.stride().is_some() {
+					channels.push(channel);
+				} else {
+					eprintln!("Skipping invalid channel of animation {}", name);
+				}
+			}
+		}
+		
+		let duration = channels.iter()
+		                       .filter_map(|channel| channel.times.last())
+		                       .cloned()
+		                       .fold(0.0, f32::max);
+		
+		Clip { name, channels, rest, duration }
+	}
+}
+
+fn convert_image(data: &gltf::image::Data) -> Option<DynamicImage> {
+	let (width, height, pixels) = (data.width, data.height, data.pixels.clone());
+	
+	Some(match data.format {
+		Format::R8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, pixels)?),
+		Format::R8G8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, pixels)?),
+		Format::R8G8B8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, pixels)?),
+		Format::R8G8B8A8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, pixels)?),
+		Format::B8G8R8 => DynamicImage::ImageBgr8(ImageBuffer::from_raw(width, height, pixels)?),
+		Format::B8G8R8A8 => DynamicImage::ImageBgra8(ImageBuffer::from_raw(width, height, pixels)?),
+		_ => {
+			eprintln!("Unsupported glTF image format {:?}", data.format);
+			return None;
+		},
+	})
+}
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+	#[error(display = "Mesh has {} vertices, at most 65536 are supported", _0)] TooManyVertices(usize),
+	#[error(display = "Skin has {} joints, at most 64 are supported", _0)] TooManyJoints(usize),
+	#[error(display = "Skin joint isn't part of the imported scene")] MissingJoint,
+	#[error(display = "Vertex uses joint {} of a skin with {} joints", _0, _1)] InvalidJoint(usize, usize),
+	#[error(display = "{}", _0)] GltfError(#[error(source)] gltf::Error),
+	#[error(display = "{}", _0)] ModelError(#[error(source)] ModelError),
+}
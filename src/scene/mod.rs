@@ -0,0 +1,299 @@
+use err_derive::Error;
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::renderer::lod::LodModel;
+
+pub mod file;
+pub mod import;
+
+/// Slot of a node and the generation of that slot. Slots of removed nodes are reused with a new generation,
+/// so ids held past the removal don't resolve to the new node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId {
+	index: usize,
+	generation: u32,
+}
+
+struct Slot {
+	generation: u32,
+	node: Option<Node>,
+}
+
+pub struct Node {
+	pub name: String,
+	/// Transform relative to the parent node.
+	pub transform: Matrix4<f32>,
+	/// Hidden nodes hide their whole subtree.
+	pub visible: bool,
+	pub model: Option<LodModel>,
+	/// Joints deforming the model.
+	pub skin: Option<Skin>,
+	/// Weights of the model's morph targets.
+	pub morph_weights: Vec<f32>,
+	/// Can be picked up with the controllers.
+	pub grabbable: bool,
+	/// Multiplies opacity of the whole subtree, fully transparent nodes aren't drawn.
+	pub opacity: f32,
+	parent: Option<NodeId>,
+	children: Vec<NodeId>,
+}
+
+impl Node {
+	pub fn parent(&self) -> Option<NodeId> {
+		self.parent
+	}
+	
+	pub fn children(&self) -> &[NodeId] {
+		&self.children
+	}
+}
+
+/// Nodes whose transforms deform a skinned model.
+#[derive(Debug, Clone)]
+pub struct Skin {
+	pub joints: Vec<NodeId>,
+	/// Transforms from the model's space to the rest space of each joint.
+	pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+/// Model to draw, with its world transform and deformation.
+pub struct Draw<'a> {
+	pub model: &'a LodModel,
+	pub transform: Matrix4<f32>,
+	/// Joint matrices relative to the model, empty if the model isn't skinned.
+	pub joints: Vec<Matrix4<f32>>,
+	pub morph_weights: &'a [f32],
+	pub opacity: f32,
+}
+
+/// Hierarchy of named nodes, each optionally holding a model. Nodes follow the transform of their parent.
+pub struct Scene {
+	/// Removed nodes leave empty slots, so ids of other nodes stay valid.
+	nodes: Vec<Slot>,
+	/// Empty slots, reused by new nodes.
+	free: Vec<usize>,
+	root: NodeId,
+}
+
+impl Scene {
+	pub fn new() -> Scene {
+		Scene {
+			nodes: vec![Slot {
+				generation: 0,
+				node: Some(Node {
+					name: "root".to_string(),
+					transform: Matrix4::identity(),
+					visible: true,
+					model: None,
+					skin: None,
+					morph_weights: Vec::new(),
+					grabbable: false,
+					opacity: 1.0,
+					parent: None,
+					children: Vec::new(),
+				}),
+			}],
+			free: Vec::new(),
+			root: NodeId { index: 0, generation: 0 },
+		}
+	}
+	
+	pub fn root(&self) -> NodeId {
+		self.root
+	}
+	
+	pub fn add(&mut self, parent: NodeId, name: impl Into<String>, model: Option<LodModel>, transform: Matrix4<f32>) -> NodeId {
+		let index = match self.free.pop() {
+			Some(index) => index,
+			None => {
+				self.nodes.push(Slot { generation: 0, node: None });
+				self.nodes.len() - 1
+			},
+		};
+		
+		let slot = &mut self.nodes[index];
+		let id = NodeId { index, generation: slot.generation };
+		
+		slot.node = Some(Node {
+			name: name.into(),
+			transform,
+			visible: true,
+			model,
+			skin: None,
+			morph_weights: Vec::new(),
+			grabbable: false,
+			opacity: 1.0,
+			parent: Some(parent),
+			children: Vec::new(),
+		});
+		
+		self.node_mut(parent).children.push(id);
+		
+		id
+	}
+	
+	/// Removes the node with all its children. Root can't be removed.
+	pub fn remove(&mut self, id: NodeId) {
+		if id == self.root {
+			return;
+		}
+		
+		let node = match self.nodes.get_mut(id.index).filter(|slot| slot.generation == id.generation).and_then(|slot| slot.node.take()) {
+			Some(node) => node,
+			None => return,
+		};
+		
+		self.nodes[id.index].generation += 1;
+		self.free.push(id.index);
+		
+		if let Some(parent) = node.parent.and_then(|parent| self.get_mut(parent)) {
+			parent.children.retain(|&child| child != id);
+		}
+		
+		for child in node.children {
+			// Parent is already gone, so children don't need to be unlinked from it
+			self.node_mut(child).parent = None;
+			self.remove(child);
+		}
+	}
+	
+	pub fn get(&self, id: NodeId) -> Option<&Node> {
+		self.nodes.get(id.index)
+		          .filter(|slot| slot.generation == id.generation)
+		          .and_then(|slot| slot.node.as_ref())
+	}
+	
+	pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+		self.nodes.get_mut(id.index)
+		          .filter(|slot| slot.generation == id.generation)
+		          .and_then(|slot| slot.node.as_mut())
+	}
+	
+	/// First node with given name, in depth-first order.
+	pub fn find(&self, name: &str) -> Option<NodeId> {
+		self.descendants(self.root).find(|&id| self.node(id).name == name)
+	}
+	
+	/// Moves the node under a new parent, keeping its local transform. Fails if the parent is the node itself or its descendant.
+	pub fn attach(&mut self, id: NodeId, parent: NodeId) -> Result<(), SceneError> {
+		if self.get(id).is_none() || self.get(parent).is_none() {
+			return Err(SceneError::InvalidNode);
+		}
+		if id == self.root || self.descendants(id).any(|descendant| descendant == parent) {
+			return Err(SceneError::Cycle);
+		}
+		
+		if let Some(old_parent) = self.node(id).parent {
+			self.node_mut(old_parent).children.retain(|&child| child != id);
+		}
+		
+		self.node_mut(parent).children.push(id);
+		self.node_mut(id).parent = Some(parent);
+		
+		Ok(())
+	}
+	
+	/// Transform of the node relative to the root.
+	pub fn world_transform(&self, id: NodeId) -> Matrix4<f32> {
+		let mut transform = Matrix4::identity();
+		let mut current = Some(id);
+		
+		while let Some(node) = current.and_then(|id| self.get(id)) {
+			transform = node.transform * transform;
+			current = node.parent;
+		}
+		
+		transform
+	}
+	
+	/// Whether the node and all its ancestors are visible.
+	pub fn is_visible(&self, id: NodeId) -> bool {
+		let mut current = Some(id);
+		
+		while let Some(node) = current.and_then(|id| self.get(id)) {
+			if !node.visible {
+				return false;
+			}
+			current = node.parent;
+		}
+		
+		true
+	}
+	
+	/// Models of all visible nodes with their world transforms. Translucent models come last, so they blend over opaque ones.
+	pub fn draw_list(&self) -> Vec<Draw> {
+		let mut list = Vec::new();
+		self.collect(self.root, Matrix4::identity(), 1.0, &mut list);
+		list.sort_by_key(|draw| draw.opacity < 1.0);
+		list
+	}
+	
+	fn collect<'a>(&'a self, id: NodeId, parent_transform: Matrix4<f32>, parent_opacity: f32, list: &mut Vec<Draw<'a>>) {
+		let node = self.node(id);
+		let opacity = parent_opacity * node.opacity;
+		if !node.visible || opacity <= 0.0 {
+			return;
+		}
+		
+		let transform = parent_transform * node.transform;
+		
+		if let Some(model) = &node.model {
+			list.push(Draw {
+				model,
+				transform,
+				joints: node.skin.as_ref().map_or_else(Vec::new, |skin| self.joint_matrices(skin, transform)),
+				morph_weights: &node.morph_weights,
+				opacity,
+			});
+		}
+		
+		for &child in &node.children {
+			self.collect(child, transform, opacity, list);
+		}
+	}
+	
+	/// Joint transforms relative to a model with given world transform.
+	fn joint_matrices(&self, skin: &Skin, transform: Matrix4<f32>) -> Vec<Matrix4<f32>> {
+		let inverse = match transform.invert() {
+			Some(inverse) => inverse,
+			None => return Vec::new(),
+		};
+		
+		skin.joints
+		    .iter()
+		    .zip(&skin.inverse_bind_matrices)
+		    .map(|(&joint, inverse_bind)| inverse * self.world_transform(joint) * inverse_bind)
+		    .collect()
+	}
+	
+	/// The node and all nodes below it, depth-first.
+	pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+		let mut stack = vec![id];
+		
+		std::iter::from_fn(move || {
+			let id = stack.pop()?;
+			stack.extend(self.node(id).children.iter().rev());
+			Some(id)
+		})
+	}
+	
+	fn node(&self, id: NodeId) -> &Node {
+		self.get(id).expect("Invalid scene node")
+	}
+	
+	fn node_mut(&mut self, id: NodeId) -> &mut Node {
+		self.get_mut(id).expect("Invalid scene node")
+	}
+}
+
+impl Default for Scene {
+	fn default() -> Scene {
+		Scene::new()
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum SceneError {
+	#[error(display = "Node doesn't exist")] InvalidNode,
+	#[error(display = "Node can't be attached to itself or its descendant")] Cycle,
+}
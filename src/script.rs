@@ -0,0 +1,309 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+use rhai::{Engine, Scope, AST, Dynamic, Array, Map, EvalAltResult, CallFnOptions, FLOAT};
+use cgmath::{Matrix4, Vector2, Vector3, Quaternion, InnerSpace, SquareMatrix, One, Deg, Rad};
+
+use crate::scene::{Scene, NodeId};
+use crate::behaviour::Frame;
+use crate::input::Action;
+use crate::eye_rotation::{EyeRotation, RotationMode, from_angles, to_angles};
+
+/// Seconds between checks for modified script files.
+const RELOAD_INTERVAL: f32 = 0.5;
+/// Operations a script can run in a single call before it's stopped, so an endless loop doesn't freeze the frame loop.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// State of the current frame, shared with functions exposed to scripts.
+struct Context {
+	/// Swapped with the application's scene while scripts run.
+	scene: Scene,
+	hmd: Matrix4<f32>,
+	/// Axis, held and pressed state of every action.
+	actions: HashMap<Action, (Vector2<f32>, bool, bool)>,
+	eye_rotation: EyeRotation,
+	eye_mode: Option<RotationMode>,
+}
+
+struct Script {
+	path: PathBuf,
+	modified: Option<SystemTime>,
+	reload: bool,
+	ast: Option<AST>,
+	scope: Scope<'static>,
+	/// Bound to `this` in script functions, kept between frames.
+	state: Dynamic,
+	/// Scripts which failed at runtime are paused until they are reloaded.
+	failed: bool,
+}
+
+/// Rhai scripts run every frame after behaviours. Scripts are reloaded when their file changes, errors are reported
+/// and pause the failing script without stopping the application.
+pub struct Scripts {
+	engine: Engine,
+	context: Rc<RefCell<Context>>,
+	scripts: Vec<Script>,
+	since_reload_check: f32,
+}
+
+impl Scripts {
+	pub fn new() -> Scripts {
+		let context = Rc::new(RefCell::new(Context {
+			scene: Scene::new(),
+			hmd: Matrix4::identity(),
+			actions: HashMap::new(),
+			eye_rotation: (Quaternion::one(), Quaternion::one()),
+			eye_mode: None,
+		}));
+		
+		let mut engine = Engine::new();
+		engine.set_max_operations(MAX_OPERATIONS);
+		register_functions(&mut engine, &context);
+		
+		Scripts {
+			engine,
+			context,
+			scripts: Vec::new(),
+			since_reload_check: 0.0,
+		}
+	}
+	
+	/// Adds a script, loaded on the next update. Its top level code runs once per load, then `fn update(dt)` every frame.
+	pub fn add(&mut self, path: PathBuf) {
+		self.scripts.push(Script {
+			path,
+			modified: None,
+			reload: true,
+			ast: None,
+			scope: Scope::new(),
+			state: Dynamic::UNIT,
+			failed: false,
+		});
+	}
+	
+	/// Runs scripts, which can move scene nodes and override eye rotation of this frame.
+	/// Returns eye rotation mode requested by the scripts.
+	pub fn update(&mut self, scene: &mut Scene, frame: &Frame, eye_rotation: &mut EyeRotation) -> Option<RotationMode> {
+		if self.scripts.is_empty() {
+			return None;
+		}
+		
+		self.since_reload_check += frame.dt;
+		if self.since_reload_check >= RELOAD_INTERVAL {
+			self.since_reload_check = 0.0;
+			
+			for script in &mut self.scripts {
+				if modified(&script.path) != script.modified {
+					script.reload = true;
+				}
+			}
+		}
+		
+		{
+			let mut context = self.context.borrow_mut();
+			mem::swap(&mut context.scene, scene);
+			context.hmd = frame.hmd;
+			context.eye_rotation = *eye_rotation;
+			context.eye_mode = None;
+			context.actions = Action::ALL.iter()
+			                             .map(|&action| (action, (frame.input.axis(action), frame.input.held(action), frame.input.pressed(action))))
+			                             .collect();
+		}
+		
+		for script in &mut self.scripts {
+			if script.reload {
+				script.load(&self.engine);
+			}
+			
+			script.run(&self.engine, frame.dt);
+		}
+		
+		let mut context = self.context.borrow_mut();
+		mem::swap(&mut context.scene, scene);
+		*eye_rotation = context.eye_rotation;
+		context.eye_mode
+	}
+}
+
+impl Script {
+	fn load(&mut self, engine: &Engine) {
+		self.reload = false;
+		self.modified = modified(&self.path);
+		self.failed = false;
+		self.state = Dynamic::from_map(Map::new());
+		self.scope = Scope::new();
+		
+		let scope = &mut self.scope;
+		let result = engine.compile_file(self.path.clone())
+		                   .and_then(|ast| engine.run_ast_with_scope(scope, &ast).map(|_| ast));
+		
+		self.ast = match result {
+			Ok(ast) => {
+				println!("Loaded script {}", self.path.display());
+				Some(ast)
+			},
+			Err(err) => {
+				eprintln!("Script {}: {}", self.path.display(), err);
+				None
+			},
+		};
+	}
+	
+	fn run(&mut self, engine: &Engine, dt: f32) {
+		let ast = match &self.ast {
+			Some(ast) if !self.failed => ast,
+			_ => return,
+		};
+		
+		if !ast.iter_functions().any(|function| function.name == "update") {
+			return;
+		}
+		
+		let options = CallFnOptions::new().bind_this_ptr(&mut self.state)
+		                                  .eval_ast(false);
+		
+		if let Err(err) = engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, ast, "update", (dt as FLOAT,)) {
+			eprintln!("Script {}: {}, paused until it's modified", self.path.display(), err);
+			self.failed = true;
+		}
+	}
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Exposes the scene, HMD pose, controller actions and eye rotation to scripts. Angles are in degrees.
+fn register_functions(engine: &mut Engine, context: &Rc<RefCell<Context>>) {
+	let ctx = context.clone();
+	engine.register_fn("has_node", move |name: &str| ctx.borrow().scene.find(name).is_some());
+	
+	let ctx = context.clone();
+	engine.register_fn("position", move |name: &str| -> ScriptResult<Array> {
+		let context = ctx.borrow();
+		let node = find(&context.scene, name)?;
+		Ok(vector(context.scene.get(node).unwrap().transform.w.truncate()))
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("world_position", move |name: &str| -> ScriptResult<Array> {
+		let context = ctx.borrow();
+		let node = find(&context.scene, name)?;
+		Ok(vector(context.scene.world_transform(node).w.truncate()))
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("set_position", move |name: &str, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+		let mut context = ctx.borrow_mut();
+		let scene = &mut context.scene;
+		let node = find(scene, name)?;
+		scene.get_mut(node).unwrap().transform.w = Vector3::new(x, y, z).extend(1.0);
+		Ok(())
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("rotate", move |name: &str, x: FLOAT, y: FLOAT, z: FLOAT, degrees: FLOAT| -> ScriptResult<()> {
+		let axis = Vector3::new(x, y, z);
+		if axis.magnitude2() == 0.0 {
+			return Err("Rotation axis can't be zero".into());
+		}
+		
+		let mut context = ctx.borrow_mut();
+		let scene = &mut context.scene;
+		let node = scene.get_mut(find(scene, name)?).unwrap();
+		node.transform = node.transform * Matrix4::from_axis_angle(axis.normalize(), Deg(degrees));
+		Ok(())
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("visible", move |name: &str| -> ScriptResult<bool> {
+		let context = ctx.borrow();
+		Ok(context.scene.get(find(&context.scene, name)?).unwrap().visible)
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("set_visible", move |name: &str, visible: bool| -> ScriptResult<()> {
+		let mut context = ctx.borrow_mut();
+		let scene = &mut context.scene;
+		scene.get_mut(find(scene, name)?).unwrap().visible = visible;
+		Ok(())
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("attach", move |name: &str, parent: &str| -> ScriptResult<()> {
+		let mut context = ctx.borrow_mut();
+		let scene = &mut context.scene;
+		let (node, parent_node) = (find(scene, name)?, find(scene, parent)?);
+		scene.attach(node, parent_node).map_err(|err| format!("Can't attach {} to {}: {}", name, parent, err).into())
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("hmd_position", move || vector(ctx.borrow().hmd.w.truncate()));
+	
+	let ctx = context.clone();
+	engine.register_fn("hmd_forward", move || vector(-ctx.borrow().hmd.z.truncate()));
+	
+	let ctx = context.clone();
+	engine.register_fn("axis", move |action: &str| -> ScriptResult<Array> {
+		let (axis, _, _) = ctx.borrow().actions[&parse_action(action)?];
+		Ok(vec![Dynamic::from(axis.x), Dynamic::from(axis.y)])
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("held", move |action: &str| -> ScriptResult<bool> {
+		Ok(ctx.borrow().actions[&parse_action(action)?].1)
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("pressed", move |action: &str| -> ScriptResult<bool> {
+		Ok(ctx.borrow().actions[&parse_action(action)?].2)
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("eye_angles", move |eye: &str| -> ScriptResult<Array> {
+		let context = ctx.borrow();
+		let rotation = if parse_eye(eye)? { context.eye_rotation.1 } else { context.eye_rotation.0 };
+		Ok(vector(to_angles(rotation).map(|angle| Deg::from(Rad(angle)).0)))
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("set_eye_angles", move |eye: &str, pitch: FLOAT, yaw: FLOAT, roll: FLOAT| -> ScriptResult<()> {
+		let rotation = from_angles(Vector3::new(pitch, yaw, roll).map(|angle| Rad::from(Deg(angle)).0));
+		let mut context = ctx.borrow_mut();
+		if parse_eye(eye)? { context.eye_rotation.1 = rotation } else { context.eye_rotation.0 = rotation }
+		Ok(())
+	});
+	
+	let ctx = context.clone();
+	engine.register_fn("set_eye_mode", move |mode: &str| -> ScriptResult<()> {
+		ctx.borrow_mut().eye_mode = Some(mode.parse()?);
+		Ok(())
+	});
+}
+
+fn find(scene: &Scene, name: &str) -> ScriptResult<NodeId> {
+	scene.find(name).ok_or_else(|| format!("Unknown node: {}", name).into())
+}
+
+fn parse_action(name: &str) -> ScriptResult<Action> {
+	Ok(name.parse()?)
+}
+
+/// Whether the eye name refers to the right eye.
+fn parse_eye(name: &str) -> ScriptResult<bool> {
+	match name {
+		"left" => Ok(false),
+		"right" => Ok(true),
+		_ => Err(format!("Unknown eye: {}, expected left or right", name).into()),
+	}
+}
+
+fn vector(vector: Vector3<f32>) -> Array {
+	vec![Dynamic::from(vector.x), Dynamic::from(vector.y), Dynamic::from(vector.z)]
+}
@@ -12,3 +12,31 @@ pub mod frag {
 		path: "src/shaders/frag.glsl"
 	}
 }
+
+pub mod fullscreen_vert {
+	vulkano_shaders::shader! {
+		ty: "vertex",
+		path: "src/shaders/fullscreen.vert.glsl"
+	}
+}
+
+pub mod upscale_frag {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		path: "src/shaders/upscale.frag.glsl"
+	}
+}
+
+pub mod vignette_frag {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		path: "src/shaders/vignette.frag.glsl"
+	}
+}
+
+pub mod stereo_frag {
+	vulkano_shaders::shader! {
+		ty: "fragment",
+		path: "src/shaders/stereo.frag.glsl"
+	}
+}
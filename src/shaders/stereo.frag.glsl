@@ -0,0 +1,43 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D left_eye;
+layout(set = 0, binding = 1) uniform sampler2D right_eye;
+
+// Same order as StereoMode, wiggle stereo is drawn as alternating left and right
+const uint LEFT = 0u;
+const uint RIGHT = 1u;
+const uint PARALLEL = 2u;
+const uint CROSS_EYE = 3u;
+const uint ANAGLYPH = 4u;
+const uint INTERLACED = 5u;
+
+layout(push_constant) uniform Params {
+	vec2 uv_scale;
+	uint mode;
+} params;
+
+void main() {
+	vec2 eye_uv = uv * params.uv_scale;
+	vec2 half_uv = vec2(fract(uv.x * 2.0), uv.y) * params.uv_scale;
+	bool left_half = uv.x < 0.5;
+	bool even_row = (int(gl_FragCoord.y) & 1) == 0;
+	
+	if(params.mode == LEFT) {
+		f_color = texture(left_eye, eye_uv);
+	} else if(params.mode == RIGHT) {
+		f_color = texture(right_eye, eye_uv);
+	} else if(params.mode == PARALLEL) {
+		f_color = left_half ? texture(left_eye, half_uv) : texture(right_eye, half_uv);
+	} else if(params.mode == CROSS_EYE) {
+		f_color = left_half ? texture(right_eye, half_uv) : texture(left_eye, half_uv);
+	} else if(params.mode == ANAGLYPH) {
+		f_color = vec4(texture(left_eye, eye_uv).r, texture(right_eye, eye_uv).gb, 1.0);
+	} else {
+		f_color = even_row ? texture(left_eye, eye_uv) : texture(right_eye, eye_uv);
+	}
+	
+	f_color.a = 1.0;
+}
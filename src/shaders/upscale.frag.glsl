@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D periphery_color;
+layout(set = 0, binding = 1) uniform sampler2D periphery_depth;
+
+layout(push_constant) uniform Params {
+	vec4 fovea;
+	vec2 uv_scale;
+} params;
+
+void main() {
+	if(all(greaterThanEqual(uv, params.fovea.xy)) && all(lessThan(uv, params.fovea.zw))) discard;
+	
+	f_color = texture(periphery_color, uv * params.uv_scale);
+	gl_FragDepth = texture(periphery_depth, uv * params.uv_scale).r;
+}
@@ -1,14 +1,44 @@
 #version 450
 
+const uint MAX_JOINTS = 64u;
+const uint MAX_MORPH_TARGETS = 8u;
+
 layout(location = 0) in vec3 pos;
 layout(location = 1) in vec2 uv;
+layout(location = 2) in uvec4 joints;
+layout(location = 3) in vec4 weights;
 layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out float opacity;
 
 layout(push_constant) uniform Mats {
 	mat4 mpv;
+	uint morph_targets;
+	float opacity;
 } mats;
 
+// Joint matrices relative to the model. Rigid models use identity in joint 0 with full weight.
+layout(set = 1, binding = 0) uniform Skin {
+	mat4 joints[MAX_JOINTS];
+	vec4 morph_weights[MAX_MORPH_TARGETS / 4u];
+} skin;
+
+// Position offsets of every morph target, grouped by vertex
+layout(set = 2, binding = 0) readonly buffer Morphs {
+	vec4 offsets[];
+} morphs;
+
 void main() {
-	gl_Position = mats.mpv * vec4(pos, 1.0);
+	vec3 position = pos;
+	for(uint morph = 0u; morph < mats.morph_targets; morph++) {
+		position += skin.morph_weights[morph / 4u][morph % 4u] * morphs.offsets[uint(gl_VertexIndex) * mats.morph_targets + morph].xyz;
+	}
+	
+	mat4 skinning = weights.x * skin.joints[joints.x]
+	              + weights.y * skin.joints[joints.y]
+	              + weights.z * skin.joints[joints.z]
+	              + weights.w * skin.joints[joints.w];
+	
+	gl_Position = mats.mpv * skinning * vec4(position, 1.0);
 	tex_coords = uv;
+	opacity = mats.opacity;
 }
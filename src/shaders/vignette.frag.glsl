@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform Params {
+	float strength;
+} params;
+
+void main() {
+	float dist = length(uv * 2.0 - 1.0);
+	float inner = 1.0 - 0.8 * params.strength;
+	
+	f_color = vec4(0.0, 0.0, 0.0, params.strength * smoothstep(inner, inner + 0.4, dist));
+}